@@ -0,0 +1,239 @@
+use crate::branch_delete::{archive, is_merged, REMOTE_ARCHIVE_PREFIX};
+use crate::command_chain::{Risk, RunStepResult, Step};
+use crate::git::{open_repo, Git};
+use crate::journal::Journal;
+use colored::*;
+use git2::{BranchType, Repository};
+
+/// Update and delete branches on origin in a single `git push --atomic`, so either every ref is
+/// updated or none are.
+///
+/// Remote branches are only deleted if they exist and their work is in the branch they were
+/// merged into, otherwise they are left alone. Their tips are kept under
+/// `refs/api-git/archive-origin/<branch>` first. Branches with nothing new to push, or updated by
+/// a unit that failed, are left out.
+#[derive(Default)]
+pub struct AtomicPush {
+    /// Branches with the unit that updates them, if any.
+    updates: Vec<(String, Option<String>)>,
+    deletes: Vec<(String, String)>,
+}
+
+impl AtomicPush {
+    pub fn new() -> AtomicPush {
+        AtomicPush::default()
+    }
+
+    /// Push the local branch to the remote branch of the same name.
+    pub fn update(&mut self, branch: &str) {
+        self.updates.push((branch.to_string(), None));
+    }
+
+    /// Push the branch updated by `unit`, unless the unit failed.
+    pub fn update_from_unit(&mut self, branch: &str, unit: &str) {
+        self.updates
+            .push((branch.to_string(), Some(unit.to_string())));
+    }
+
+    /// Delete the remote branch, which has been merged into `target`.
+    pub fn delete(&mut self, branch: &str, target: &str) {
+        self.deletes.push((branch.to_string(), target.to_string()));
+    }
+
+    fn refspecs(&self, repo: &Repository, failed_units: &[String]) -> Result<Vec<String>, String> {
+        let mut refspecs = vec![];
+
+        for (branch, unit) in &self.updates {
+            if let Some(unit) = unit.as_ref().filter(|unit| failed_units.contains(unit)) {
+                println!("{} didn't succeed, not pushing {}", unit, branch);
+                continue;
+            }
+
+            if repo.find_branch(branch, BranchType::Local).is_err() {
+                println!("{} doesn't exist locally, not pushing it", branch);
+                continue;
+            }
+
+            if has_new_commits(repo, branch) {
+                refspecs.push(branch.clone());
+            }
+        }
+
+        for (branch, target) in &self.deletes {
+            let tip = match repo.refname_to_id(&format!("refs/remotes/origin/{}", branch)) {
+                Ok(tip) => tip,
+                Err(_) => {
+                    println!("origin/{} doesn't exist, not deleting it", branch);
+                    continue;
+                }
+            };
+
+            let target_oid = repo
+                .refname_to_id(&format!("refs/heads/{}", target))
+                .map_err(|e| e.to_string())?;
+
+            // Like after resolving conflicts in a rebase. Leaving the branch is better than
+            // failing the push of everything else
+            if !is_merged(repo, tip, target_oid) {
+                println!(
+                    "{}",
+                    format!(
+                        "origin/{} has commits that aren't in {}, not deleting it",
+                        branch, target
+                    )
+                    .yellow()
+                );
+                continue;
+            }
+
+            archive(repo, REMOTE_ARCHIVE_PREFIX, branch, tip)?;
+            refspecs.push(format!(":{}", branch));
+        }
+
+        Ok(refspecs)
+    }
+}
+
+/// Whether the local branch has commits that aren't on origin. Branches that are behind are left
+/// out of the push rather than making all of it fail.
+fn has_new_commits(repo: &Repository, branch: &str) -> bool {
+    let local = repo.refname_to_id(&format!("refs/heads/{}", branch));
+    let remote = repo.refname_to_id(&format!("refs/remotes/origin/{}", branch));
+
+    match (local, remote) {
+        (Ok(local), Ok(remote)) => {
+            local != remote && !repo.graph_descendant_of(remote, local).unwrap_or(false)
+        }
+        _ => true,
+    }
+}
+
+impl Step for AtomicPush {
+    fn run_step(&self) -> RunStepResult {
+        let failed_units = Journal::load()
+            .map(|journal| journal.failed_units)
+            .unwrap_or_default();
+
+        match self.refspecs(&open_repo(), &failed_units) {
+            Ok(ref refspecs) if refspecs.is_empty() => RunStepResult::Ok,
+            Ok(refspecs) => Git::atomic_push(&refspecs).run_step(),
+            Err(err) => {
+                eprintln!("{}", err.red());
+                RunStepResult::Err
+            }
+        }
+    }
+
+    fn as_string(&self) -> String {
+        let mut refspecs = self
+            .updates
+            .iter()
+            .map(|(branch, _)| branch.clone())
+            .collect::<Vec<_>>();
+        for (branch, _) in &self.deletes {
+            refspecs.push(format!(":{}", branch));
+        }
+        Git::atomic_push(&refspecs).as_string()
+    }
+
+    fn risk(&self) -> Risk {
+        if self.deletes.is_empty() {
+            Risk::RemoteWrite
+        } else {
+            Risk::Destructive
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    #[allow(unused_imports)]
+    use super::*;
+    use git2::{Oid, Signature};
+
+    fn commit(repo: &Repository, parent: Option<Oid>, contents: &str) -> Oid {
+        let blob = repo.blob(contents.as_bytes()).unwrap();
+        let mut tree = repo.treebuilder(None).unwrap();
+        tree.insert("file", blob, 0o100644).unwrap();
+        let tree = repo.find_tree(tree.write().unwrap()).unwrap();
+
+        let signature = Signature::now("Tester", "tester@example.com").unwrap();
+        let parents = parent.map(|oid| repo.find_commit(oid).unwrap());
+        let parents = parents.iter().collect::<Vec<_>>();
+        repo.commit(None, &signature, &signature, "update", &tree, &parents)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_deleting_branches_is_destructive() {
+        let mut push = AtomicPush::new();
+        push.update("master");
+        assert_eq!(push.risk(), Risk::RemoteWrite);
+
+        push.delete("feature", "master");
+        assert_eq!(push.risk(), Risk::Destructive);
+    }
+
+    #[test]
+    fn test_unmerged_remote_branches_are_kept() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init(dir.path()).unwrap();
+
+        let master = commit(&repo, None, "master");
+        let merged = commit(&repo, Some(master), "merged");
+        let unmerged = commit(&repo, Some(master), "unmerged");
+        repo.reference("refs/heads/master", merged, true, "")
+            .unwrap();
+        repo.reference("refs/remotes/origin/master", master, true, "")
+            .unwrap();
+        repo.reference("refs/remotes/origin/merged", merged, true, "")
+            .unwrap();
+        repo.reference("refs/remotes/origin/unmerged", unmerged, true, "")
+            .unwrap();
+
+        let mut push = AtomicPush::new();
+        push.update("master");
+        push.delete("merged", "master");
+        push.delete("unmerged", "master");
+        push.delete("unpushed", "master");
+
+        assert_eq!(
+            push.refspecs(&repo, &[]),
+            Ok(vec!["master".to_string(), ":merged".to_string()])
+        );
+        assert_eq!(
+            repo.refname_to_id("refs/api-git/archive-origin/merged"),
+            Ok(merged)
+        );
+        assert!(repo
+            .refname_to_id("refs/api-git/archive-origin/unmerged")
+            .is_err());
+    }
+
+    #[test]
+    fn test_failed_units_and_missing_branches_are_left_out() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init(dir.path()).unwrap();
+
+        let base = commit(&repo, None, "base");
+        let ahead = commit(&repo, Some(base), "ahead");
+        for branch in &["master", "staging", "develop"] {
+            repo.reference(&format!("refs/remotes/origin/{}", branch), base, true, "")
+                .unwrap();
+        }
+        for branch in &["master", "staging"] {
+            repo.reference(&format!("refs/heads/{}", branch), ahead, true, "")
+                .unwrap();
+        }
+
+        let mut push = AtomicPush::new();
+        push.update("master");
+        push.update_from_unit("staging", "master into staging");
+        push.update_from_unit("develop", "master into develop");
+
+        assert_eq!(
+            push.refspecs(&repo, &["master into staging".to_string()]),
+            Ok(vec!["master".to_string()])
+        );
+    }
+}
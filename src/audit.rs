@@ -0,0 +1,177 @@
+use crate::config::Config;
+use crate::journal::state_dir;
+use crate::report::{Report, StepStatus};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// One run of api-git, as recorded in the audit log.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub id: String,
+    pub user: String,
+    pub email: Option<String>,
+    pub time: String,
+    /// The arguments api-git was called with.
+    pub args: Vec<String>,
+    pub dry_run: bool,
+    pub succeeded: bool,
+    pub steps: Vec<AuditStep>,
+    /// The refs the run moved, created or deleted, as they were before it.
+    pub refs_before: BTreeMap<String, String>,
+    /// The same refs after the run.
+    pub refs_after: BTreeMap<String, String>,
+    /// Set when the run went ahead even though it broke the merge policy.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub policy_override: Option<PolicyOverride>,
+    /// The reason given for going ahead even though a branch was frozen.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub freeze_override: Option<String>,
+    /// The rules that were broken, when the merge policy stopped the run before it started.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub policy_refused: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PolicyOverride {
+    pub reason: String,
+    /// The rules that were broken.
+    pub violations: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AuditStep {
+    pub index: usize,
+    pub command: String,
+    pub status: StepStatus,
+    pub duration_ms: u64,
+}
+
+impl AuditEntry {
+    pub fn from_report(report: &Report, dry_run: bool) -> AuditEntry {
+        let before = report.refs_before.to_strings();
+        let after = report.refs_after.to_strings();
+
+        AuditEntry {
+            dry_run,
+            succeeded: report.succeeded(),
+            steps: report
+                .steps
+                .iter()
+                .map(|step| AuditStep {
+                    index: step.index,
+                    command: step.command.clone(),
+                    status: step.status,
+                    duration_ms: step.duration.as_millis() as u64,
+                })
+                .collect(),
+            refs_before: changed(&before, &after),
+            refs_after: changed(&after, &before),
+            ..AuditEntry::now()
+        }
+    }
+
+    /// A run the merge policy stopped before any step ran.
+    pub fn refused(violations: Vec<String>) -> AuditEntry {
+        AuditEntry {
+            policy_refused: violations,
+            ..AuditEntry::now()
+        }
+    }
+
+    /// An entry for a run started now, by the current user, that didn't run anything.
+    fn now() -> AuditEntry {
+        // The run may have checked out an invalid `.api-git`, which shouldn't lose the entry
+        let config = Config::load().ok();
+        let now = chrono::Local::now();
+
+        AuditEntry {
+            id: format!(
+                "{}-{:04x}",
+                now.format("%Y%m%d-%H%M%S"),
+                now.timestamp_subsec_micros() & 0xffff
+            ),
+            user: config
+                .as_ref()
+                .and_then(|config| config.get_str("user.name"))
+                .or_else(|| std::env::var("USER").ok())
+                .unwrap_or_default(),
+            email: config
+                .as_ref()
+                .and_then(|config| config.get_str("user.email")),
+            time: now.to_rfc3339_opts(chrono::SecondsFormat::Secs, false),
+            args: std::env::args().skip(1).collect(),
+            dry_run: false,
+            succeeded: false,
+            steps: vec![],
+            refs_before: BTreeMap::new(),
+            refs_after: BTreeMap::new(),
+            policy_override: None,
+            freeze_override: None,
+            policy_refused: vec![],
+        }
+    }
+}
+
+/// The refs in `refs` that are different or missing in `other`.
+fn changed(
+    refs: &BTreeMap<String, String>,
+    other: &BTreeMap<String, String>,
+) -> BTreeMap<String, String> {
+    refs.iter()
+        .filter(|(name, sha)| other.get(*name) != Some(sha))
+        .map(|(name, sha)| (name.clone(), sha.clone()))
+        .collect()
+}
+
+/// The audit log in `.git/api-git/audit.jsonl`.
+pub fn local_log() -> PathBuf {
+    state_dir().join("audit.jsonl")
+}
+
+/// The audit log shared by the team, set with `api-git.auditLog`. Fails if the config is invalid.
+pub fn shared_log() -> Result<Option<PathBuf>, String> {
+    Ok(Config::load()?
+        .get_str("api-git.auditLog")
+        .map(PathBuf::from))
+}
+
+/// Append the entry to the local audit log, and to the shared one if configured.
+pub fn record(entry: &AuditEntry) {
+    let line = serde_json::to_string(entry).expect("failed to serialize audit entry");
+
+    let shared_log = shared_log().unwrap_or_else(|err| {
+        eprintln!("Failed to write the shared audit log: {}", err);
+        None
+    });
+
+    for path in Some(local_log()).into_iter().chain(shared_log) {
+        if let Err(err) = append_line(&path, &line) {
+            eprintln!("Failed to write audit log {}: {}", path.display(), err);
+        }
+    }
+}
+
+/// All entries in an audit log, oldest first.
+pub fn read_log(path: &Path) -> Vec<AuditEntry> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(_) => return vec![],
+    };
+
+    contents
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect()
+}
+
+fn append_line(path: &Path, line: &str) -> std::io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    writeln!(file, "{}", line)
+}
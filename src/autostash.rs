@@ -0,0 +1,97 @@
+use crate::command::Command;
+use crate::command_chain::{RunStepResult, Step};
+use crate::git::{open_repo, Git};
+use crate::journal::Journal;
+use colored::*;
+
+const STASH_MESSAGE: &str = "api-git-autostash";
+
+/// Stash local changes, including untracked files, and record the stash in the journal.
+pub struct Autostash;
+
+impl Step for Autostash {
+    fn run_step(&self) -> RunStepResult {
+        let before = stash_tip();
+
+        let output = Git::from(format!(
+            "stash push --include-untracked -m {}",
+            STASH_MESSAGE
+        ))
+        .execute();
+        if !output.status.success() {
+            return RunStepResult::Err;
+        }
+
+        let after = stash_tip();
+        if after != before {
+            Journal::update(|journal| journal.autostash = after);
+        }
+
+        RunStepResult::Ok
+    }
+
+    fn as_string(&self) -> String {
+        format!("git stash push --include-untracked -m {}", STASH_MESSAGE)
+    }
+}
+
+/// Apply and drop the stash recorded in the journal by `Autostash`, if any.
+pub struct RestoreAutostash;
+
+impl Step for RestoreAutostash {
+    fn run_step(&self) -> RunStepResult {
+        let oid = match Journal::load().and_then(|journal| journal.autostash) {
+            Some(oid) => oid,
+            None => return RunStepResult::Ok,
+        };
+
+        let index = match stash_index(&oid) {
+            Some(index) => index,
+            None => {
+                println!(
+                    "{}",
+                    format!("Stash {} no longer exists, nothing to restore", oid).yellow()
+                );
+                Journal::update(|journal| journal.autostash = None);
+                return RunStepResult::Ok;
+            }
+        };
+
+        let output = Git::from(format!("stash pop stash@{{{}}}", index)).execute();
+        if !output.status.success() {
+            return RunStepResult::Err;
+        }
+
+        Journal::update(|journal| journal.autostash = None);
+        RunStepResult::Ok
+    }
+
+    fn as_string(&self) -> String {
+        "git stash pop".to_string()
+    }
+}
+
+fn stash_tip() -> Option<String> {
+    open_repo()
+        .refname_to_id("refs/stash")
+        .ok()
+        .map(|oid| oid.to_string())
+}
+
+/// The index of the stash commit `oid` in the stash list, if it's still there.
+pub fn stash_index(oid: &str) -> Option<usize> {
+    let mut repo = open_repo();
+    let mut found = None;
+
+    repo.stash_foreach(|index, _message, stash_oid| {
+        if stash_oid.to_string() == oid {
+            found = Some(index);
+            false
+        } else {
+            true
+        }
+    })
+    .expect("failed to list stashes");
+
+    found
+}
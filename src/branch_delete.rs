@@ -0,0 +1,159 @@
+use crate::command_chain::{RunStepResult, Step};
+use crate::git::open_repo;
+use colored::*;
+use git2::{BranchType, Commit, DiffFormat, Oid, Repository};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashSet;
+use std::hash::{Hash, Hasher};
+
+/// Where deleted branches are kept, so they can be restored later.
+pub const ARCHIVE_PREFIX: &str = "refs/api-git/archive/";
+/// Where the tips of branches deleted on origin are kept.
+pub const REMOTE_ARCHIVE_PREFIX: &str = "refs/api-git/archive-origin/";
+
+/// Delete a local branch, but only if all its work made it into `target`.
+///
+/// The tip is kept under `refs/api-git/archive/<branch>` before the branch is deleted.
+pub struct SafeDeleteBranch {
+    branch: String,
+    target: String,
+}
+
+impl SafeDeleteBranch {
+    pub fn new(branch: &str, target: &str) -> SafeDeleteBranch {
+        SafeDeleteBranch {
+            branch: branch.to_string(),
+            target: target.to_string(),
+        }
+    }
+
+    fn delete(&self, repo: &Repository) -> Result<(), String> {
+        let mut branch = match repo.find_branch(&self.branch, BranchType::Local) {
+            Ok(branch) => branch,
+            Err(_) => {
+                println!("{} doesn't exist, nothing to delete", self.branch);
+                return Ok(());
+            }
+        };
+
+        let tip = branch.get().target().ok_or("branch has no target")?;
+        let target = repo
+            .refname_to_id(&format!("refs/heads/{}", self.target))
+            .map_err(|e| e.to_string())?;
+
+        if !is_merged(repo, tip, target) {
+            return Err(format!(
+                "{} has commits that aren't in {}, not deleting it",
+                self.branch, self.target
+            ));
+        }
+
+        archive(repo, ARCHIVE_PREFIX, &self.branch, tip)?;
+        branch.delete().map_err(|e| e.to_string())?;
+        println!("Deleted {} (was {})", self.branch, tip);
+
+        Ok(())
+    }
+}
+
+impl Step for SafeDeleteBranch {
+    fn run_step(&self) -> RunStepResult {
+        match self.delete(&open_repo()) {
+            Ok(()) => RunStepResult::Ok,
+            Err(err) => {
+                eprintln!("{}", err.red());
+                RunStepResult::Err
+            }
+        }
+    }
+
+    fn as_string(&self) -> String {
+        format!(
+            "delete {} if merged into {} (archived in {}{})",
+            self.branch, self.target, ARCHIVE_PREFIX, self.branch
+        )
+    }
+}
+
+/// Keep the tip of a branch that is about to be deleted under `prefix`.
+pub fn archive(repo: &Repository, prefix: &str, branch: &str, tip: Oid) -> Result<(), String> {
+    repo.reference(
+        &format!("{}{}", prefix, branch),
+        tip,
+        true,
+        &format!("api-git: archive {}", branch),
+    )
+    .map(|_| ())
+    .map_err(|e| e.to_string())
+}
+
+/// Whether all the work on `tip` is in `target`. Either because `tip` is reachable from `target`,
+/// or because every commit missing from `target` has a patch-equivalent commit in it, which is
+/// the case after rebasing.
+pub fn is_merged(repo: &Repository, tip: Oid, target: Oid) -> bool {
+    if tip == target || repo.graph_descendant_of(target, tip).unwrap_or(false) {
+        return true;
+    }
+
+    let missing = match commits_between(repo, target, tip) {
+        Some(commits) => commits,
+        None => return false,
+    };
+    let candidates = match commits_between(repo, tip, target) {
+        Some(commits) => commits,
+        None => return false,
+    };
+
+    let patch_ids = candidates
+        .iter()
+        .filter_map(|oid| patch_id(repo, *oid))
+        .collect::<HashSet<_>>();
+
+    missing.iter().all(|oid| match patch_id(repo, *oid) {
+        Some(id) => patch_ids.contains(&id),
+        None => false,
+    })
+}
+
+/// Commits reachable from `to` but not from `from`.
+fn commits_between(repo: &Repository, from: Oid, to: Oid) -> Option<Vec<Oid>> {
+    let mut walk = repo.revwalk().ok()?;
+    walk.push(to).ok()?;
+    walk.hide(from).ok()?;
+    walk.collect::<Result<Vec<_>, _>>().ok()
+}
+
+/// A hash of the changes a commit makes, ignoring where they are in the file. Commits with the
+/// same patch ID make the same change, like with `git patch-id`.
+fn patch_id(repo: &Repository, oid: Oid) -> Option<u64> {
+    let commit: Commit = repo.find_commit(oid).ok()?;
+    if commit.parent_count() > 1 {
+        return None;
+    }
+
+    let tree = commit.tree().ok()?;
+    let parent_tree = match commit.parent(0) {
+        Ok(parent) => Some(parent.tree().ok()?),
+        Err(_) => None,
+    };
+
+    let diff = repo
+        .diff_tree_to_tree(parent_tree.as_ref(), Some(&tree), None)
+        .ok()?;
+
+    let mut hasher = DefaultHasher::new();
+    diff.print(DiffFormat::Patch, |delta, _hunk, line| {
+        match line.origin() {
+            '+' | '-' => {
+                delta.new_file().path().hash(&mut hasher);
+                line.origin().hash(&mut hasher);
+                line.content().hash(&mut hasher);
+            }
+            _ => {}
+        }
+        true
+    })
+    .ok()?;
+
+    Some(hasher.finish())
+}
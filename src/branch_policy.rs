@@ -0,0 +1,295 @@
+use crate::config::Config;
+use regex::Regex;
+
+const DEFAULT_TICKET_PATTERN: &str = "[A-Z][A-Z0-9]+-[0-9]+";
+const SLUG_PATTERN: &str = "[a-z0-9]+(-[a-z0-9]+)*";
+
+/// Rules new branch names must follow. Configured with the `api-git.branch.*` keys.
+///
+/// - `pattern`: A regex the full branch name must match.
+/// - `requireTicket`: Whether the name must contain a ticket ID.
+/// - `ticketPattern`: The regex used to find ticket IDs. Defaults to `[A-Z][A-Z0-9]+-[0-9]+`.
+/// - `prefix`: An allowed prefix, such as `feature/`. Can be given multiple times.
+/// - `maxLength`: Longer names are truncated.
+/// - `template`: How names are built, such as `{user}/{ticket}-{slug}`. Supports `{user}`,
+///   `{ticket}`, `{slug}`, `{base}` and `{prefix}`.
+#[derive(Debug)]
+pub struct BranchPolicy {
+    pattern: Option<Regex>,
+    require_ticket: bool,
+    ticket_pattern: Regex,
+    prefixes: Vec<String>,
+    max_length: Option<usize>,
+    template: Option<String>,
+}
+
+/// Values available to the branch name template.
+#[derive(Debug)]
+pub struct TemplateVars {
+    pub user: String,
+    pub ticket: Option<String>,
+    pub base: String,
+}
+
+impl BranchPolicy {
+    pub fn from_config(config: &Config) -> Result<BranchPolicy, String> {
+        let pattern = config
+            .get_str("api-git.branch.pattern")
+            .map(|pattern| parse_regex("api-git.branch.pattern", &format!("^(?:{})$", pattern)))
+            .transpose()?;
+
+        let ticket_pattern = config
+            .get_str("api-git.branch.ticketPattern")
+            .unwrap_or_else(|| DEFAULT_TICKET_PATTERN.to_string());
+
+        Ok(BranchPolicy {
+            pattern,
+            require_ticket: config
+                .get_bool("api-git.branch.requireTicket")
+                .unwrap_or(false),
+            ticket_pattern: parse_regex("api-git.branch.ticketPattern", &ticket_pattern)?,
+            prefixes: config.get_all("api-git.branch.prefix"),
+            max_length: config.get_count("api-git.branch.maxLength")?,
+            template: config.get_str("api-git.branch.template"),
+        })
+    }
+
+    /// Turn what the user typed into a branch name that follows the policy.
+    ///
+    /// Names that already follow the policy are returned unchanged. Everything else is built from
+    /// the template, or slugified if there is no template. Returns the rules that are still broken
+    /// after that.
+    pub fn apply(&self, input: &str, vars: &TemplateVars) -> Result<String, Vec<String>> {
+        let input = input.trim();
+
+        let mut name = match &self.template {
+            Some(template) if !self.matches_template(template, input, vars) => {
+                self.render(template, input, vars)?
+            }
+            _ if is_plain(input) => input.to_string(),
+            _ => slugify_path(input),
+        };
+
+        if let Some(max_length) = self.max_length {
+            name = truncate(&name, max_length);
+        }
+
+        let violations = self.violations(&name);
+        if violations.is_empty() {
+            Ok(name)
+        } else {
+            Err(violations)
+        }
+    }
+
+    /// The rules the given branch name breaks.
+    pub fn violations(&self, name: &str) -> Vec<String> {
+        let mut violations = vec![];
+
+        if let Some(pattern) = &self.pattern {
+            if !pattern.is_match(name) {
+                violations.push(format!(
+                    "{} doesn't match the pattern {}",
+                    name,
+                    pattern.as_str()
+                ));
+            }
+        }
+
+        if self.require_ticket && !self.ticket_pattern.is_match(name) {
+            violations.push(format!(
+                "{} doesn't contain a ticket ID. Pass one with --ticket",
+                name
+            ));
+        }
+
+        if !self.prefixes.is_empty() && self.allowed_prefix(name).is_none() {
+            violations.push(format!(
+                "{} must start with one of {}",
+                name,
+                self.prefixes.join(", ")
+            ));
+        }
+
+        violations
+    }
+
+    fn allowed_prefix<'a>(&'a self, name: &str) -> Option<&'a str> {
+        self.prefixes
+            .iter()
+            .find(|prefix| name.starts_with(prefix.as_str()))
+            .map(|prefix| prefix.as_str())
+    }
+
+    fn render(
+        &self,
+        template: &str,
+        input: &str,
+        vars: &TemplateVars,
+    ) -> Result<String, Vec<String>> {
+        let prefix = self.allowed_prefix(input).unwrap_or("");
+        let text = &input[prefix.len()..];
+
+        let ticket = vars.ticket.clone().or_else(|| {
+            self.ticket_pattern
+                .find(text)
+                .map(|ticket| ticket.as_str().to_string())
+        });
+
+        if template.contains("{ticket}") && ticket.is_none() {
+            return Err(vec![format!(
+                "The branch name template {} requires a ticket ID. Pass one with --ticket",
+                template
+            )]);
+        }
+
+        let text = match &ticket {
+            Some(ticket) => text.replace(ticket.as_str(), " "),
+            None => text.to_string(),
+        };
+
+        let name = template
+            .replace("{user}", &vars.user)
+            .replace("{ticket}", ticket.as_deref().unwrap_or(""))
+            .replace("{base}", &vars.base)
+            .replace("{slug}", &slugify(&text));
+
+        if template.contains("{prefix}") {
+            Ok(name.replace("{prefix}", prefix))
+        } else {
+            Ok(format!("{}{}", prefix, name))
+        }
+    }
+
+    fn matches_template(&self, template: &str, input: &str, vars: &TemplateVars) -> bool {
+        let mut pattern = regex::escape(template);
+
+        for (placeholder, replacement) in &[
+            ("{user}", regex::escape(&vars.user)),
+            ("{ticket}", format!("(?:{})", self.ticket_pattern.as_str())),
+            ("{base}", regex::escape(&vars.base)),
+            ("{slug}", SLUG_PATTERN.to_string()),
+            ("{prefix}", "(?:[^/]+/)?".to_string()),
+        ] {
+            pattern = pattern.replace(&regex::escape(placeholder), replacement);
+        }
+
+        if !template.contains("{prefix}") {
+            pattern = format!("(?:[^/]+/)?{}", pattern);
+        }
+
+        Regex::new(&format!("^{}$", pattern))
+            .map(|regex| regex.is_match(input))
+            .unwrap_or(false)
+    }
+}
+
+/// Turn free text into something usable in a branch name.
+///
+/// `"Fix login timeout"` becomes `"fix-login-timeout"`.
+pub fn slugify(text: &str) -> String {
+    text.to_lowercase()
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+/// Like `slugify` but keeps `/` so `feature/Fix login` becomes `feature/fix-login`.
+fn slugify_path(text: &str) -> String {
+    text.split('/')
+        .map(slugify)
+        .filter(|segment| !segment.is_empty())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn is_plain(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "/._-".contains(c))
+}
+
+/// Cut `name` to at most `max_length` characters.
+fn truncate(name: &str, max_length: usize) -> String {
+    match name.char_indices().nth(max_length) {
+        Some((end, _)) => name[..end].trim_end_matches(&['-', '/'][..]).to_string(),
+        None => name.to_string(),
+    }
+}
+
+pub fn parse_regex(key: &str, pattern: &str) -> Result<Regex, String> {
+    Regex::new(pattern).map_err(|err| format!("Invalid regex in {}: {}", key, err))
+}
+
+#[cfg(test)]
+mod test {
+    #[allow(unused_imports)]
+    use super::*;
+
+    fn policy() -> BranchPolicy {
+        BranchPolicy {
+            pattern: None,
+            require_ticket: true,
+            ticket_pattern: Regex::new(DEFAULT_TICKET_PATTERN).unwrap(),
+            prefixes: vec!["feature/".to_string(), "fix/".to_string()],
+            max_length: Some(40),
+            template: Some("{user}/{ticket}-{slug}".to_string()),
+        }
+    }
+
+    fn vars(ticket: Option<&str>) -> TemplateVars {
+        TemplateVars {
+            user: "alice".to_string(),
+            ticket: ticket.map(|s| s.to_string()),
+            base: "master".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_slugify() {
+        assert_eq!(slugify("Fix login timeout"), "fix-login-timeout");
+        assert_eq!(slugify("  Don't  crash!! "), "don-t-crash");
+        assert_eq!(slugify_path("feature/Fix login"), "feature/fix-login");
+    }
+
+    #[test]
+    fn test_apply_renders_template() {
+        assert_eq!(
+            policy().apply("fix/Fix login timeout", &vars(Some("API-12"))),
+            Ok("fix/alice/API-12-fix-login-timeout".to_string()),
+        );
+        assert_eq!(
+            policy().apply("feature/API-7 Add search", &vars(None)),
+            Ok("feature/alice/API-7-add-search".to_string()),
+        );
+    }
+
+    #[test]
+    fn test_truncate() {
+        assert_eq!(truncate("fix-login-timeout", 10), "fix-login");
+        assert_eq!(truncate("alice/fix", 6), "alice");
+        assert_eq!(truncate("jürgen/fix", 3), "jür");
+        assert_eq!(truncate("fix", 10), "fix");
+    }
+
+    #[test]
+    fn test_ticket_pattern_is_case_sensitive() {
+        assert!(policy()
+            .apply("feature/Fix utf-8 names", &vars(None))
+            .is_err());
+    }
+
+    #[test]
+    fn test_apply_keeps_conforming_names() {
+        let name = "fix/alice/API-12-fix-login-timeout";
+        assert_eq!(policy().apply(name, &vars(None)), Ok(name.to_string()));
+    }
+
+    #[test]
+    fn test_apply_rejects_invalid_names() {
+        assert!(policy().apply("fix/Fix login", &vars(None)).is_err());
+        assert!(policy().apply("Fix login", &vars(Some("API-1"))).is_err());
+    }
+}
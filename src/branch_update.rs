@@ -0,0 +1,102 @@
+use crate::command_chain::{RunStepResult, Step};
+use crate::git::{head_branch, open_repo, Git};
+use colored::*;
+use git2::{BranchType, Repository};
+
+/// Fast-forward a local branch to its remote-tracking branch, which must already be fetched.
+///
+/// Branches that aren't checked out are updated without checking them out, and branches that
+/// aren't on origin yet are left as they are. Fails if the local and remote branches have
+/// diverged.
+pub struct FastForwardFromRemote {
+    branch: String,
+}
+
+impl FastForwardFromRemote {
+    pub fn new(branch: &str) -> FastForwardFromRemote {
+        FastForwardFromRemote {
+            branch: branch.to_string(),
+        }
+    }
+
+    fn remote_branch(&self) -> String {
+        format!("origin/{}", self.branch)
+    }
+
+    fn update(&self, repo: &Repository) -> Result<(), String> {
+        let remote = match repo.refname_to_id(&format!("refs/remotes/{}", self.remote_branch())) {
+            Ok(oid) => oid,
+            Err(_) => {
+                println!("{} doesn't exist, nothing to update", self.remote_branch());
+                return Ok(());
+            }
+        };
+
+        let local = match repo.find_branch(&self.branch, BranchType::Local) {
+            Ok(branch) => branch,
+            Err(_) => {
+                let commit = repo.find_commit(remote).map_err(|e| e.to_string())?;
+                let mut branch = repo
+                    .branch(&self.branch, &commit, false)
+                    .map_err(|e| e.to_string())?;
+                branch
+                    .set_upstream(Some(&self.remote_branch()))
+                    .map_err(|e| e.to_string())?;
+                println!("Created {} from {}", self.branch, self.remote_branch());
+                return Ok(());
+            }
+        };
+
+        let local_oid = local.get().target().ok_or("branch has no target")?;
+
+        if local_oid == remote || repo.graph_descendant_of(local_oid, remote).unwrap_or(false) {
+            println!("{} is up to date", self.branch);
+            return Ok(());
+        }
+
+        if !repo.graph_descendant_of(remote, local_oid).unwrap_or(false) {
+            return Err(format!(
+                "{} has diverged from {}. Merge or rebase it manually",
+                self.branch,
+                self.remote_branch()
+            ));
+        }
+
+        local
+            .into_reference()
+            .set_target(
+                remote,
+                &format!("api-git: fast-forward to {}", self.remote_branch()),
+            )
+            .map_err(|e| e.to_string())?;
+        println!("Fast-forwarded {} to {}", self.branch, remote);
+
+        Ok(())
+    }
+}
+
+impl Step for FastForwardFromRemote {
+    fn run_step(&self) -> RunStepResult {
+        let repo = open_repo();
+        let remote_exists = repo
+            .find_branch(&self.remote_branch(), BranchType::Remote)
+            .is_ok();
+
+        // The working tree has to be updated as well, which git does best
+        if remote_exists && head_branch().as_ref() == Some(&self.branch) {
+            return Git::fast_forward_merge(&self.remote_branch()).run_step();
+        }
+
+        match self.update(&repo) {
+            Ok(()) => RunStepResult::Ok,
+            Err(err) => {
+                eprintln!("{}", err.red());
+                RunStepResult::Err
+            }
+        }
+    }
+
+    fn as_string(&self) -> String {
+        format!("fast-forward {} to {}", self.branch, self.remote_branch())
+    }
+}
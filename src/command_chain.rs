@@ -1,14 +1,42 @@
+use crate::autostash::{Autostash, RestoreAutostash};
 use crate::command::Command;
+use crate::journal::Journal;
+use crate::native::NativeGit;
+use crate::report::{RefState, Report, StepRecord, StepStatus};
+use crate::snapshot::Snapshot;
 use crate::string_ext::*;
 use colored::*;
+use std::cell::RefCell;
+use std::ops::Range;
+use std::time::{Duration, Instant};
 
+#[derive(Default)]
 pub struct CommandChain {
     steps: Vec<Box<dyn Step>>,
+    units: Vec<Unit>,
+}
+
+/// A group of steps that is independent of the rest of the chain. If one of its steps fail the
+/// unit is rolled back and the chain continues after it, skipping the units that come after it.
+struct Unit {
+    name: String,
+    steps: Range<usize>,
+    /// The units this one builds on.
+    after: Vec<String>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum UnitStatus {
+    Succeeded,
+    Failed,
+    Skipped,
+    /// Not run because a unit it comes after failed.
+    Blocked,
 }
 
 impl CommandChain {
     pub fn new() -> CommandChain {
-        CommandChain { steps: vec![] }
+        CommandChain::default()
     }
 
     pub fn add<S>(&mut self, step: S)
@@ -18,59 +46,330 @@ impl CommandChain {
         self.steps.push(Box::new(step));
     }
 
-    pub fn run_and_print_from_step(&self, idx: usize, rerun_command: &str, runner: &StepRunner) {
+    /// Group the steps added by `f` into an independent unit. It only runs if the units named in
+    /// `after` didn't fail.
+    pub fn unit<F>(&mut self, name: &str, after: &[String], f: F)
+    where
+        F: FnOnce(&mut CommandChain),
+    {
+        let start = self.steps.len();
+        f(self);
+        let end = self.steps.len();
+
+        self.units.push(Unit {
+            name: name.to_string(),
+            steps: start..end,
+            after: after.to_vec(),
+        });
+    }
+
+    /// Stash local changes before the first step, and restore them after the last step.
+    pub fn autostash(&mut self) {
+        let mut stash = CommandChain::new();
+        stash.add(Autostash);
+        self.prepend(stash);
+        self.add(RestoreAutostash);
+    }
+
+    /// Put the steps of `other` before the steps of this chain.
+    pub fn prepend(&mut self, mut other: CommandChain) {
+        let len = other.steps.len();
+        for unit in &mut self.units {
+            unit.steps = unit.steps.start + len..unit.steps.end + len;
+        }
+
+        other.steps.append(&mut self.steps);
+        other.units.append(&mut self.units);
+        *self = other;
+    }
+
+    /// Every step with its risk.
+    pub fn plan(&self) -> Vec<(String, Risk)> {
+        self.steps
+            .iter()
+            .map(|step| (step.as_string(), step.risk()))
+            .collect()
+    }
+
+    /// The steps that would run when starting from `idx`, without running or printing anything.
+    pub fn record_from_step(&self, idx: usize) -> Vec<String> {
+        let recorder = StepRunner::recorder();
+        self.run_and_print_from_step(idx, "", &recorder);
+        recorder.recorded()
+    }
+
+    pub fn run_and_print_from_step(
+        &self,
+        idx: usize,
+        rerun_command: &str,
+        runner: &StepRunner,
+    ) -> Report {
+        let started = Instant::now();
+        let capture_refs = || {
+            if runner.is_quiet() {
+                RefState::default()
+            } else {
+                RefState::capture()
+            }
+        };
+        let refs_before = capture_refs();
+        let mut records = vec![];
+        let mut result = RunStepResult::Ok;
+        let mut statuses = vec![UnitStatus::Skipped; self.units.len()];
+        let mut snapshot = None;
+
         for (step, cmd) in self.steps.iter().enumerate() {
+            let mut record = StepRecord {
+                index: step,
+                command: cmd.as_string(),
+                status: StepStatus::Skipped,
+                duration: Duration::default(),
+                deploy: cmd.is_deploy(),
+            };
+
+            let unit = self
+                .units
+                .iter()
+                .position(|unit| unit.steps.contains(&step));
+
+            if let RunStepResult::Err = result {
+                record.status = StepStatus::NotRun;
+                records.push(record);
+                continue;
+            }
+
             if step < idx {
+                records.push(record);
                 continue;
             }
 
-            println!(
-                "{}",
-                format!(
-                    "-- Running step {}: {}",
-                    step.to_string().green(),
-                    cmd.as_string(),
-                )
-                .green(),
-            );
-            match runner.run_step(&**cmd) {
-                RunStepResult::Ok => {}
-                RunStepResult::Err => {
+            if let Some(unit) = unit {
+                if step == idx || step == self.units[unit].steps.start {
+                    if self.is_blocked(unit, &statuses) {
+                        statuses[unit] = UnitStatus::Blocked;
+                        self.record_failure(unit, true, runner);
+                    } else {
+                        statuses[unit] = UnitStatus::Succeeded;
+                        self.record_failure(unit, false, runner);
+                        if !matches!(runner, StepRunner::Dry | StepRunner::Record(_)) {
+                            snapshot = Some(Snapshot::take());
+                        }
+                    }
+                }
+
+                if matches!(statuses[unit], UnitStatus::Failed | UnitStatus::Blocked) {
+                    records.push(record);
+                    continue;
+                }
+            }
+
+            if !runner.is_quiet() {
+                println!(
+                    "{}",
+                    format!(
+                        "-- Running step {}: {}",
+                        step.to_string().green(),
+                        cmd.as_string(),
+                    )
+                    .green(),
+                );
+            }
+
+            let step_started = Instant::now();
+            let step_result = runner.run_step(&**cmd);
+            record.duration = step_started.elapsed();
+            record.status = match (&step_result, runner) {
+                (RunStepResult::Err, _) => StepStatus::Failed,
+                (RunStepResult::Ok, StepRunner::Dry)
+                | (RunStepResult::Ok, StepRunner::Record(_)) => StepStatus::DryRun,
+                (RunStepResult::Ok, _) => StepStatus::Succeeded,
+            };
+            records.push(record);
+
+            if let RunStepResult::Err = step_result {
+                if let Some(unit) = unit {
+                    statuses[unit] = UnitStatus::Failed;
+                    self.roll_back(unit, snapshot.take());
+                    self.record_failure(unit, true, runner);
+
                     println!(
                         "{}",
-                        format!("Step {} failed. Fix the problem and rerun with:", step).red(),
+                        format!(
+                            "Step {} failed. The rest of the chain goes on, fix the problem and run {} again with:",
+                            step, self.units[unit].name
+                        )
+                        .red(),
                     );
                     println!();
 
-                    let path_to_self = "api-git";
-                    let retry_command =
-                        format!("{} {} --from-step {}", path_to_self, rerun_command, step);
+                    let retry_command = self.unit_retry_command(unit, rerun_command);
                     println!("{}", retry_command.indent(2));
 
-                    copy_to_clipboard(&retry_command);
-                    println!("Retry command has been copied to the clipboard");
+                    if copy_to_clipboard(&retry_command) {
+                        println!("Retry command has been copied to the clipboard");
+                    }
+                    println!();
+                    continue;
+                }
+
+                println!(
+                    "{}",
+                    format!("Step {} failed. Fix the problem and rerun with:", step).red(),
+                );
+                println!();
 
-                    break;
+                let retry_command = retry_command(rerun_command, step);
+                println!("{}", retry_command.indent(2));
+
+                if copy_to_clipboard(&retry_command) {
+                    println!("Retry command has been copied to the clipboard");
                 }
+
+                result = RunStepResult::Err;
+                continue;
+            }
+            if !runner.is_quiet() {
+                println!();
+            }
+        }
+
+        if !runner.is_quiet() {
+            self.print_unit_summary(&statuses, rerun_command);
+        }
+
+        // The chain ran to the end, but the run as a whole failed
+        if statuses
+            .iter()
+            .any(|status| matches!(status, UnitStatus::Failed | UnitStatus::Blocked))
+        {
+            result = RunStepResult::Err;
+        }
+
+        Report {
+            command: rerun_command.to_string(),
+            result,
+            steps: records,
+            refs_before,
+            refs_after: capture_refs(),
+            duration: started.elapsed(),
+        }
+    }
+
+    fn roll_back(&self, unit: usize, snapshot: Option<Snapshot>) {
+        let name = &self.units[unit].name;
+        println!("{}", format!("{} failed, rolling it back", name).red());
+
+        if let Some(snapshot) = snapshot {
+            if let Err(err) = snapshot.restore() {
+                println!("{}", format!("Failed to roll back {}: {}", name, err).red());
+            }
+        }
+    }
+
+    /// Keep track of the failed units in the journal, for steps that must leave their work out.
+    /// A unit that runs again when resuming is taken out until it fails again.
+    fn record_failure(&self, unit: usize, failed: bool, runner: &StepRunner) {
+        if matches!(runner, StepRunner::Dry | StepRunner::Record(_)) {
+            return;
+        }
+
+        let name = &self.units[unit].name;
+        Journal::update(|journal| {
+            journal
+                .failed_units
+                .retain(|failed_unit| failed_unit != name);
+            if failed {
+                journal.failed_units.push(name.clone());
+            }
+        });
+    }
+
+    /// Whether one of the units `unit` comes after failed or was skipped because of that.
+    fn is_blocked(&self, unit: usize, statuses: &[UnitStatus]) -> bool {
+        self.units.iter().zip(statuses).any(|(other, status)| {
+            self.units[unit].after.contains(&other.name)
+                && matches!(status, UnitStatus::Failed | UnitStatus::Blocked)
+        })
+    }
+
+    /// Resuming from the step before the unit runs the whole unit again.
+    fn unit_retry_command(&self, unit: usize, rerun_command: &str) -> String {
+        match self.units[unit].steps.start {
+            0 => format!("api-git {}", rerun_command),
+            start => retry_command(rerun_command, start - 1),
+        }
+    }
+
+    fn print_unit_summary(&self, statuses: &[UnitStatus], rerun_command: &str) {
+        if self.units.is_empty() {
+            return;
+        }
+
+        let width = self
+            .units
+            .iter()
+            .map(|unit| unit.name.len())
+            .max()
+            .unwrap_or(0);
+
+        println!();
+        println!("{}", "Summary:".bold());
+        for (idx, (unit, status)) in self.units.iter().zip(statuses).enumerate() {
+            let name = format!("{:width$}", unit.name, width = width);
+            match status {
+                UnitStatus::Succeeded => println!("  {}  {}", name, "succeeded".green()),
+                UnitStatus::Skipped => println!("  {}  {}", name, "skipped".yellow()),
+                UnitStatus::Blocked => println!(
+                    "  {}  {}   because {} didn't succeed",
+                    name,
+                    "skipped".yellow(),
+                    unit.after.join(", ")
+                ),
+                UnitStatus::Failed => println!(
+                    "  {}  {}     {}",
+                    name,
+                    "failed".red(),
+                    self.unit_retry_command(idx, rerun_command)
+                ),
             }
-            println!();
         }
     }
 }
 
-fn copy_to_clipboard(text: &str) {
-    use clipboard::{ClipboardContext, ClipboardProvider};
+fn retry_command(rerun_command: &str, step: usize) -> String {
+    let path_to_self = "api-git";
+    format!("{} {} --from-step {}", path_to_self, rerun_command, step)
+}
 
-    let mut cp = ClipboardContext::new().expect("failed to create clipboard");
+/// Returns whether the text was copied. There is no clipboard when running over SSH or in CI.
+fn copy_to_clipboard(text: &str) -> bool {
+    use clipboard::{ClipboardContext, ClipboardProvider};
 
-    cp.set_contents(text.to_string())
-        .expect("failed to copy to clipboard");
+    match ClipboardContext::new() {
+        Ok(mut cp) => cp.set_contents(text.to_string()).is_ok(),
+        Err(_) => false,
+    }
 }
 
 pub trait Step {
     fn run_step(&self) -> RunStepResult;
 
     fn as_string(&self) -> String;
+
+    /// How much damage the step can do if it goes wrong.
+    fn risk(&self) -> Risk {
+        Risk::Local
+    }
+
+    /// Whether the step deploys to an environment.
+    fn is_deploy(&self) -> bool {
+        self.risk() == Risk::Deploy
+    }
+
+    /// The same step done through libgit2, if it has one.
+    fn native(&self) -> Option<Box<dyn Step>> {
+        None
+    }
 }
 
 impl<T> Step for T
@@ -90,6 +389,66 @@ where
     fn as_string(&self) -> String {
         format!("{} {}", self.command(), self.args().join(" "))
     }
+
+    /// Pushes to remotes other than origin, like `git push staging staging:master`, are deploys.
+    /// Force pushes, deleting branches and hard resets are destructive.
+    fn risk(&self) -> Risk {
+        let args = self.args();
+        let args = args.iter().map(String::as_str).collect::<Vec<_>>();
+        let has = |flags: &[&str]| args.iter().any(|arg| flags.contains(arg));
+
+        match args.first() {
+            Some(&"push") => {
+                let mut refs = args[1..].iter().filter(|arg| !arg.starts_with('-'));
+                if refs.next().is_some_and(|remote| *remote != "origin") {
+                    Risk::Deploy
+                } else if has(&["-f", "--force", "--force-with-lease", "-d", "--delete"])
+                    || refs.any(|refspec| refspec.starts_with(':') || refspec.starts_with('+'))
+                {
+                    Risk::Destructive
+                } else {
+                    Risk::RemoteWrite
+                }
+            }
+            Some(&"branch") if has(&["-D"]) => Risk::Destructive,
+            Some(&"reset") if has(&["--hard"]) => Risk::Destructive,
+            _ => Risk::Local,
+        }
+    }
+
+    fn native(&self) -> Option<Box<dyn Step>> {
+        NativeGit::parse(&self.command(), &self.args())
+            .map(|native| Box::new(native) as Box<dyn Step>)
+    }
+}
+
+/// How much damage a step can do, from the least to the most.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Risk {
+    /// Only changes the local repo, and can be undone.
+    Local,
+    /// Updates branches on origin.
+    RemoteWrite,
+    /// Throws away commits or branches, locally or on origin.
+    Destructive,
+    /// Deploys to an environment.
+    Deploy,
+}
+
+impl Risk {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Risk::Local => "local",
+            Risk::RemoteWrite => "remote-write",
+            Risk::Destructive => "destructive",
+            Risk::Deploy => "deploy",
+        }
+    }
+
+    /// Whether steps like this need an explicit confirmation when `api-git.confirmRisky` is set.
+    pub fn is_risky(self) -> bool {
+        matches!(self, Risk::Destructive | Risk::Deploy)
+    }
 }
 
 pub enum RunStepResult {
@@ -97,21 +456,48 @@ pub enum RunStepResult {
     Err,
 }
 
-#[allow(dead_code)]
 pub enum StepRunner {
     Dry,
     Run,
+    /// Run steps through libgit2 where possible.
+    Native,
+    /// Don't run or print anything, just collect the steps that would run.
+    Record(RefCell<Vec<String>>),
 }
 
 impl StepRunner {
+    pub fn recorder() -> StepRunner {
+        StepRunner::Record(RefCell::new(vec![]))
+    }
+
+    /// The steps collected by a recorder.
+    pub fn recorded(&self) -> Vec<String> {
+        match self {
+            StepRunner::Record(steps) => steps.borrow().clone(),
+            _ => vec![],
+        }
+    }
+
+    fn is_quiet(&self) -> bool {
+        matches!(self, StepRunner::Record(_))
+    }
+
     fn run_step(&self, step: &dyn Step) -> RunStepResult {
         match self {
+            StepRunner::Record(steps) => {
+                steps.borrow_mut().push(step.as_string());
+                RunStepResult::Ok
+            }
             StepRunner::Dry => {
                 println!("Dry run:");
                 println!("{}", step.as_string().indent(2));
                 RunStepResult::Ok
             }
             StepRunner::Run => step.run_step(),
+            StepRunner::Native => match step.native() {
+                Some(native) => native.run_step(),
+                None => step.run_step(),
+            },
         }
     }
 }
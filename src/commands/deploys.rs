@@ -0,0 +1,74 @@
+use crate::commands::RunError;
+use crate::deploys::{deploys, fetch_deploys};
+use crate::git::find_repo;
+use clap::ArgMatches;
+use colored::*;
+use git2::{Oid, Repository};
+
+/// How many commits are listed per deploy before the rest are summed up.
+const MAX_COMMITS: usize = 10;
+
+pub fn run_deploys(args: &ArgMatches) -> Result<(), RunError> {
+    let env = args.value_of("ENV").expect("ENV is required");
+
+    let repo = find_repo()?;
+    fetch_deploys();
+    let deploys = deploys(&repo, env);
+
+    if deploys.is_empty() {
+        println!("No deploys to {} have been recorded", env);
+        return Ok(());
+    }
+
+    // Newest first, each with what changed since the deploy before it
+    let previous = std::iter::once(None).chain(deploys.iter().map(Some));
+    let timeline = deploys.iter().zip(previous).collect::<Vec<_>>();
+
+    for (deploy, previous) in timeline.iter().rev() {
+        println!(
+            "{}  {}  {}  {}",
+            deploy.time.format("%Y-%m-%d %H:%M").to_string().bold(),
+            deploy.commit.to_string()[..7].yellow(),
+            deploy.branch,
+            deploy.user
+        );
+
+        match previous {
+            Some(previous) => print_changes(&repo, previous.commit, deploy.commit),
+            None => println!("  The first recorded deploy to {}", env),
+        }
+        println!();
+    }
+
+    Ok(())
+}
+
+fn print_changes(repo: &Repository, from: Oid, to: Oid) {
+    let commits = repo
+        .revwalk()
+        .and_then(|mut walk| {
+            walk.push(to)?;
+            walk.hide(from)?;
+            Ok(walk.filter_map(Result::ok).collect::<Vec<_>>())
+        })
+        .unwrap_or_default();
+
+    match commits.len() {
+        0 => println!("  Nothing changed since the previous deploy"),
+        1 => println!("  1 commit since the previous deploy:"),
+        n => println!("  {} commits since the previous deploy:", n),
+    }
+
+    for oid in commits.iter().take(MAX_COMMITS) {
+        if let Ok(commit) = repo.find_commit(*oid) {
+            println!(
+                "    {} {}",
+                oid.to_string()[..7].yellow(),
+                commit.summary().unwrap_or("")
+            );
+        }
+    }
+    if commits.len() > MAX_COMMITS {
+        println!("    ... and {} more", commits.len() - MAX_COMMITS);
+    }
+}
@@ -0,0 +1,90 @@
+use crate::command_chain::*;
+use crate::commands::*;
+use crate::freeze::{all_freezes, fetch_freezes, EndFreeze, StartFreeze};
+use crate::git::find_repo;
+use clap::ArgMatches;
+
+pub fn run_freeze(args: &ArgMatches) -> Result<(), RunError> {
+    match args.subcommand() {
+        ("start", Some(matches)) => {
+            FreezeArgs::from_args(true, matches).parse_args_and_run_command(matches, freeze_command)
+        }
+        ("end", Some(matches)) => FreezeArgs::from_args(false, matches)
+            .parse_args_and_run_command(matches, freeze_command),
+        _ => print_status(),
+    }
+}
+
+fn freeze_command(args: &FreezeArgs) -> CommandChain {
+    let mut c = CommandChain::new();
+
+    for branch in &args.branches {
+        match &args.reason {
+            Some(reason) => c.add(StartFreeze::new(branch, reason)),
+            None => c.add(EndFreeze::new(branch)),
+        }
+    }
+
+    c
+}
+
+fn print_status() -> Result<(), RunError> {
+    let repo = find_repo()?;
+    fetch_freezes();
+    let freezes = all_freezes(&repo);
+
+    if freezes.is_empty() {
+        println!("Nothing is frozen");
+    }
+    for freeze in freezes {
+        println!("{}", freeze.describe());
+    }
+
+    Ok(())
+}
+
+#[derive(Debug)]
+struct FreezeArgs {
+    branches: Vec<String>,
+    /// Set when starting a freeze.
+    reason: Option<String>,
+}
+
+impl FreezeArgs {
+    fn from_args(start: bool, args: &ArgMatches) -> Self {
+        let branches = args
+            .values_of("BRANCH")
+            .map(|branches| branches.map(ToString::to_string).collect())
+            .unwrap_or_else(|| vec!["master".to_string()]);
+
+        FreezeArgs {
+            branches,
+            reason: if start {
+                Some(
+                    args.value_of("reason")
+                        .expect("reason is required")
+                        .to_string(),
+                )
+            } else {
+                None
+            },
+        }
+    }
+}
+
+impl CommandArgs for FreezeArgs {
+    fn rerun_command(&self) -> String {
+        let mut rerun_command = match &self.reason {
+            Some(reason) => format!("freeze start --reason {}", quote(reason)),
+            None => "freeze end".to_string(),
+        };
+        for branch in &self.branches {
+            rerun_command.push_str(&format!(" {}", branch));
+        }
+        rerun_command
+    }
+
+    fn returns_to_start(&self) -> bool {
+        false
+    }
+}
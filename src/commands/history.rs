@@ -0,0 +1,130 @@
+use crate::audit::{local_log, read_log, shared_log, AuditEntry};
+use crate::commands::RunError;
+use crate::git::find_repo;
+use crate::report::StepStatus;
+use clap::ArgMatches;
+use colored::*;
+
+pub fn run_history(args: &ArgMatches) -> Result<(), RunError> {
+    find_repo()?;
+
+    let path = if args.is_present("shared") {
+        shared_log()?.ok_or_else(|| {
+            "There is no shared audit log. Set one with api-git.auditLog".to_string()
+        })?
+    } else {
+        local_log()
+    };
+
+    let entries = read_log(&path);
+
+    if let Some(matches) = args.subcommand_matches("show") {
+        let id = matches.value_of("ID").expect("ID is required");
+        match entries.iter().find(|entry| entry.id == id) {
+            Some(entry) => print_entry(entry),
+            None => return Err(format!("No run with id {} in {}", id, path.display()).into()),
+        }
+    } else {
+        for entry in entries.iter().rev() {
+            println!(
+                "{}  {}  {}  {}  api-git {}",
+                entry.id.yellow(),
+                entry.time,
+                entry.user,
+                status(entry),
+                entry.args.join(" ")
+            );
+        }
+    }
+
+    Ok(())
+}
+
+fn print_entry(entry: &AuditEntry) {
+    println!("{}    {}", "Run:".bold(), entry.id);
+    println!(
+        "{}   {} {}",
+        "User:".bold(),
+        entry.user,
+        entry
+            .email
+            .as_ref()
+            .map(|email| format!("<{}>", email))
+            .unwrap_or_default()
+    );
+    println!("{}   {}", "Time:".bold(), entry.time);
+    println!("{}    api-git {}", "Ran:".bold(), entry.args.join(" "));
+    println!("{} {}", "Status:".bold(), status(entry));
+    if let Some(policy_override) = &entry.policy_override {
+        println!(
+            "{} {}",
+            "Policy overridden:".bold(),
+            policy_override.reason.yellow()
+        );
+        for violation in &policy_override.violations {
+            println!("  {}", violation);
+        }
+    }
+    if let Some(reason) = &entry.freeze_override {
+        println!("{} {}", "Freeze overridden:".bold(), reason.yellow());
+    }
+    if !entry.policy_refused.is_empty() {
+        println!("{}", "Refused by the merge policy:".bold());
+        for violation in &entry.policy_refused {
+            println!("  {}", violation);
+        }
+    }
+    println!();
+
+    for step in &entry.steps {
+        let status = format!("{:8}", step.status.as_str());
+        let status = match step.status {
+            StepStatus::Failed => status.red(),
+            StepStatus::Skipped | StepStatus::NotRun => status.yellow(),
+            _ => status.green(),
+        };
+        println!(
+            "  {:>3}  {}  {:>6}ms  {}",
+            step.index, status, step.duration_ms, step.command
+        );
+    }
+
+    let mut printed_header = false;
+    let names = entry.refs_before.keys().chain(entry.refs_after.keys());
+    let mut seen = std::collections::BTreeSet::new();
+    for name in names {
+        if !seen.insert(name) {
+            continue;
+        }
+
+        let before = entry.refs_before.get(name);
+        let after = entry.refs_after.get(name);
+        if before == after {
+            continue;
+        }
+
+        if !printed_header {
+            println!();
+            printed_header = true;
+        }
+
+        println!(
+            "  {}  {} -> {}",
+            name.bold(),
+            before.map(|sha| &sha[..7]).unwrap_or("(none)"),
+            after.map(|sha| &sha[..7]).unwrap_or("(deleted)"),
+        );
+    }
+}
+
+fn status(entry: &AuditEntry) -> ColoredString {
+    if !entry.policy_refused.is_empty() {
+        "refused".red()
+    } else if entry.dry_run {
+        "dry run".yellow()
+    } else if entry.succeeded {
+        "ok".green()
+    } else {
+        "failed".red()
+    }
+}
@@ -0,0 +1,78 @@
+use crate::command_chain::*;
+use crate::commands::*;
+use crate::git::{find_repo, head_branch};
+use crate::lock::{all_locks, fetch_locks, ReleaseLock, StealLock};
+use clap::ArgMatches;
+
+pub fn run_lock(args: &ArgMatches) -> Result<(), RunError> {
+    match args.subcommand() {
+        ("release", Some(matches)) => {
+            LockArgs::from_args(false, matches)?.parse_args_and_run_command(matches, lock_command)
+        }
+        ("steal", Some(matches)) => {
+            LockArgs::from_args(true, matches)?.parse_args_and_run_command(matches, lock_command)
+        }
+        _ => print_status(),
+    }
+}
+
+fn lock_command(args: &LockArgs) -> CommandChain {
+    let mut c = CommandChain::new();
+
+    match &args.steal_for {
+        Some(branch) => c.add(StealLock::new(&args.env, branch)),
+        None => c.add(ReleaseLock::new(&args.env)),
+    }
+
+    c
+}
+
+fn print_status() -> Result<(), RunError> {
+    let repo = find_repo()?;
+    fetch_locks();
+    let locks = all_locks(&repo);
+
+    if locks.is_empty() {
+        println!("Nothing is locked");
+    }
+    for lock in locks {
+        println!("{}", lock.describe());
+    }
+
+    Ok(())
+}
+
+#[derive(Debug)]
+struct LockArgs {
+    env: String,
+    /// The branch the lock is taken over for, when stealing it.
+    steal_for: Option<String>,
+}
+
+impl LockArgs {
+    fn from_args(steal: bool, args: &ArgMatches) -> Result<Self, String> {
+        find_repo()?;
+
+        Ok(LockArgs {
+            env: args.value_of("ENV").expect("ENV is required").to_string(),
+            steal_for: if steal {
+                Some(head_branch().unwrap_or_default())
+            } else {
+                None
+            },
+        })
+    }
+}
+
+impl CommandArgs for LockArgs {
+    fn rerun_command(&self) -> String {
+        match &self.steal_for {
+            Some(_) => format!("lock steal {}", self.env),
+            None => format!("lock release {}", self.env),
+        }
+    }
+
+    fn returns_to_start(&self) -> bool {
+        false
+    }
+}
@@ -1,98 +1,137 @@
+use crate::atomic_push::AtomicPush;
+use crate::branch_delete::SafeDeleteBranch;
+use crate::branch_update::FastForwardFromRemote;
 use crate::command_chain::*;
 use crate::commands::*;
-use crate::git::{branch_exists, current_branch_with_confirm, ConfirmDefault, Git};
+use crate::config::Config;
+use crate::freeze::CheckFreeze;
+use crate::git::{current_branch, find_repo, ConfirmDefault, Git};
+use crate::hooks::{HookPoint, Hooks};
+use crate::merge_policy::MergePolicy;
+use crate::preview::PlannedMerge;
+use crate::propagation::{propagation_edges, Edge};
 use clap::ArgMatches;
 use std::string::ToString;
 
-pub fn run_merge(args: &ArgMatches) {
-    MergeArgs::from_args(&args)
-        .unwrap()
-        .parse_args_and_run_command(&args, merge_command);
+pub fn run_merge(args: &ArgMatches) -> Result<(), RunError> {
+    MergeArgs::from_args(&args)?.parse_args_and_run_command(&args, merge_command)
 }
 
-fn merge_command(args: &MergeArgs) -> CommandChain {
+/// The steps of `merge`. Doesn't read the repo or the terminal, that's done by `from_args`.
+pub fn merge_command(args: &MergeArgs) -> CommandChain {
     let mut c = CommandChain::new();
+    let hooks = &args.hooks;
+    let into = Some(args.into.as_str());
 
-    c.add(Git::checkout(&args.into));
-    c.add(Git::pull());
-
+    c.add(Git::fetch());
+    c.add(FastForwardFromRemote::new(&args.into));
     for branch in &args.branches {
-        c.add(Git::checkout(&branch));
-        c.add(Git::pull());
+        c.add(FastForwardFromRemote::new(branch));
+    }
 
+    for branch in &args.branches {
         if args.no_rebase {
             c.add(Git::checkout(&args.into));
+            hooks.add(&mut c, HookPoint::BeforeMerge, Some(branch), into);
             c.add(Git::merge(&branch));
         } else {
+            c.add(Git::checkout(&branch));
             c.add(Git::rebase(&args.into));
-            c.add(Git::force_push());
+            hooks.add(&mut c, HookPoint::BeforeMerge, Some(branch), into);
             c.add(Git::checkout(&args.into));
             c.add(Git::fast_forward_merge(&branch));
         }
+        hooks.add(&mut c, HookPoint::AfterMerge, Some(branch), into);
     }
 
-    c.add(Git::checkout(&args.into));
-    c.add(Git::push());
+    // Every remote ref is updated by a single atomic push at the end, so the remote is never
+    // left half updated
+    let mut push = AtomicPush::new();
+    push.update(&args.into);
+
+    for edge in &args.edges {
+        c.unit(&edge.to_string(), &edge.after(&args.edges), |c| {
+            edge.add_steps(c, hooks)
+        });
+        push.update_from_unit(&edge.into, &edge.to_string());
+    }
 
     for branch in &args.branches {
-        c.add(Git::delete_branch(branch));
-        c.add(Git::delete_remote_branch(branch));
-        c.add(Git::prune_remote());
-    }
-
-    if args.into == "master" {
-        for branch in ["staging", "develop"].iter() {
-            if branch_exists(branch) {
-                c.add(Git::checkout(branch));
-                c.add(Git::pull());
-                c.add(Git::merge(&args.into));
-                c.add(Git::push());
-            }
-        }
+        push.delete(branch, &args.into);
     }
 
-    c.add(Git::checkout(&args.into));
+    hooks.add(&mut c, HookPoint::BeforePush, None, into);
+    c.add(push);
+
+    for branch in &args.branches {
+        c.add(SafeDeleteBranch::new(branch, &args.into));
+    }
+    c.add(Git::prune_remote());
 
     c
 }
 
 #[derive(Debug)]
-struct MergeArgs {
-    no_rebase: bool,
-    into: String,
-    branches: Vec<String>,
+pub struct MergeArgs {
+    pub no_rebase: bool,
+    pub into: String,
+    pub branches: Vec<String>,
+    /// The downstream merges after `into` has been updated.
+    pub edges: Vec<Edge>,
+    pub hooks: Hooks,
+    /// No branch was given so the current branch is merged, which is confirmed before running.
+    pub current_branch: bool,
+    /// Merge even if one of the targets is frozen, for this reason.
+    pub override_freeze: Option<String>,
 }
 
 impl MergeArgs {
-    fn from_args(args: &ArgMatches) -> Option<Self> {
-        let branches = if let Some(branches) = args.values_of("BRANCH") {
-            branches.map(ToString::to_string).collect()
-        } else {
-            vec![current_branch_with_confirm(
-                |current_branch| {
-                    format!("Do you want to merge the current branch {}", current_branch)
-                },
-                ConfirmDefault::No,
-            )]
-        };
+    /// Merging `branches` into `into` with a rebase, without propagation or hooks.
+    pub fn new(into: &str, branches: &[&str]) -> MergeArgs {
+        MergeArgs {
+            no_rebase: false,
+            into: into.to_string(),
+            branches: branches.iter().map(ToString::to_string).collect(),
+            edges: vec![],
+            hooks: Hooks::none("merge"),
+            current_branch: false,
+            override_freeze: None,
+        }
+    }
 
-        let into = if let Some(into) = args.value_of("into") {
-            into.into()
-        } else {
-            "master".into()
-        };
+    /// Merging `branches` into `into` with the propagation and hooks configured for the repo in
+    /// the current directory. Fails if there is no repo or its config or propagation graph is
+    /// invalid.
+    pub fn for_repo(into: &str, branches: &[&str]) -> Result<MergeArgs, String> {
+        find_repo()?;
+
+        Ok(MergeArgs {
+            edges: propagation_edges(into)?,
+            hooks: Hooks::for_command("merge")?,
+            ..MergeArgs::new(into, branches)
+        })
+    }
 
-        let mut s = MergeArgs {
-            no_rebase: false,
-            into,
-            branches,
+    fn from_args(args: &ArgMatches) -> Result<Self, String> {
+        find_repo()?;
+
+        let branches: Vec<String> = match args.values_of("BRANCH") {
+            Some(branches) => branches.map(ToString::to_string).collect(),
+            None => vec![current_branch()],
         };
 
+        let into = args.value_of("into").unwrap_or("master");
+        let branches = branches.iter().map(String::as_str).collect::<Vec<_>>();
+
+        let mut s = MergeArgs::for_repo(into, &branches)?;
+
         if args.is_present("no-rebase") {
             s.no_rebase = true;
         }
+        s.current_branch = !args.is_present("BRANCH");
+        s.override_freeze = args.value_of("override-freeze").map(ToString::to_string);
 
-        Some(s)
+        Ok(s)
     }
 }
 
@@ -103,10 +142,104 @@ impl CommandArgs for MergeArgs {
         if self.no_rebase {
             rerun_command.push_str(" --no-rebase");
         }
+        if let Some(reason) = &self.override_freeze {
+            rerun_command.push_str(&format!(" --override-freeze {}", quote(reason)));
+        }
         rerun_command.push_str(&format!(" --into {}", self.into));
         for branch in &self.branches {
             rerun_command.push_str(&format!(" {}", branch));
         }
         rerun_command
     }
+
+    fn merges(&self) -> Vec<PlannedMerge> {
+        self.branches
+            .iter()
+            .map(|branch| PlannedMerge::new(branch, &self.into, !self.no_rebase))
+            .collect()
+    }
+
+    fn policy_violations(&self) -> Result<Vec<String>, String> {
+        Ok(MergePolicy::from_config(&Config::load()?)?.violations(&self.merges()))
+    }
+
+    fn freeze_check(&self) -> Option<CheckFreeze> {
+        let mut targets = vec![self.into.as_str()];
+        targets.extend(self.edges.iter().map(|edge| edge.into.as_str()));
+        Some(CheckFreeze::new(&targets, self.override_freeze.as_deref()))
+    }
+
+    fn confirmation(&self) -> Option<(String, ConfirmDefault)> {
+        self.current_branch.then(|| {
+            (
+                format!(
+                    "Do you want to merge the current branch {}",
+                    self.branches[0]
+                ),
+                ConfirmDefault::No,
+            )
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    #[allow(unused_imports)]
+    use super::*;
+    use crate::propagation::PropagationGraph;
+
+    fn edges(from: &str) -> Vec<Edge> {
+        PropagationGraph::parse(&["master -> staging, develop:ff-only".to_string()])
+            .unwrap()
+            .edges_from(from)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_merge_steps() {
+        let mut args = MergeArgs::new("master", &["feature"]);
+        args.edges = edges("master");
+
+        assert_eq!(
+            merge_command(&args).record_from_step(0),
+            vec![
+                "git fetch origin",
+                "fast-forward master to origin/master",
+                "fast-forward feature to origin/feature",
+                "git checkout feature",
+                "git rebase master",
+                "git checkout master",
+                "git merge --ff-only feature",
+                "fast-forward staging to origin/staging",
+                "git checkout staging",
+                "git merge --no-edit master",
+                "fast-forward develop to origin/develop",
+                "git checkout develop",
+                "git merge --ff-only master",
+                "git push --atomic origin master staging develop :feature",
+                "delete feature if merged into master (archived in refs/api-git/archive/feature)",
+                "git fetch origin --prune",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_merge_without_rebase_from_step() {
+        let mut args = MergeArgs::new("develop", &["a", "b"]);
+        args.no_rebase = true;
+
+        assert_eq!(
+            merge_command(&args).record_from_step(4),
+            vec![
+                "git checkout develop",
+                "git merge --no-edit a",
+                "git checkout develop",
+                "git merge --no-edit b",
+                "git push --atomic origin develop :a :b",
+                "delete a if merged into develop (archived in refs/api-git/archive/a)",
+                "delete b if merged into develop (archived in refs/api-git/archive/b)",
+                "git fetch origin --prune",
+            ]
+        );
+    }
 }
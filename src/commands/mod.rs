@@ -1,10 +1,70 @@
+pub mod deploys;
+pub mod freeze;
+pub mod history;
+pub mod lock;
 pub mod merge;
 pub mod on_remote;
+pub mod plugin;
+pub mod restore_branch;
 pub mod ship_hotfix;
 pub mod start;
+pub mod workflow;
 
+use crate::audit::{self, AuditEntry, PolicyOverride};
+use crate::autostash::stash_index;
 use crate::command_chain::*;
+use crate::config::Config;
+use crate::freeze::CheckFreeze;
+use crate::git::{confirm, find_repo, head_branch, head_commit, ConfirmDefault};
+use crate::hooks::{HookPoint, Hooks};
+use crate::journal::Journal;
+use crate::preview::{print_plan, PlannedMerge};
+use crate::report::Report;
+use crate::return_to_start::{restore_after_failure, ReturnToStart};
 use clap::ArgMatches;
+use colored::*;
+use std::fmt;
+use std::path::PathBuf;
+
+/// Why a command stopped without running its steps.
+#[derive(Debug)]
+pub enum RunError {
+    /// The user answered no when asked to go ahead.
+    Declined,
+    /// The command can't run, for the reason given.
+    Invalid(String),
+    /// A plugin failed with this exit code.
+    Plugin(i32),
+}
+
+impl RunError {
+    /// The exit code the CLI uses for the error.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            RunError::Declined => 0,
+            RunError::Invalid(_) => 1,
+            RunError::Plugin(code) => *code,
+        }
+    }
+}
+
+impl fmt::Display for RunError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RunError::Declined => write!(f, "Stopped without running anything"),
+            RunError::Invalid(message) => write!(f, "{}", message),
+            RunError::Plugin(code) => write!(f, "The plugin failed with exit code {}", code),
+        }
+    }
+}
+
+impl std::error::Error for RunError {}
+
+impl From<String> for RunError {
+    fn from(message: String) -> RunError {
+        RunError::Invalid(message)
+    }
+}
 
 pub trait CommandArgs
 where
@@ -12,31 +72,305 @@ where
 {
     fn rerun_command(&self) -> String;
 
-    fn parse_args_and_run_command<F>(&self, args: &ArgMatches, command: F)
+    /// Whether the chain should end by checking out the branch the user started on.
+    fn returns_to_start(&self) -> bool {
+        true
+    }
+
+    /// The merges the command does, shown with the steps before asking for confirmation.
+    fn merges(&self) -> Vec<PlannedMerge> {
+        vec![]
+    }
+
+    /// The question to ask before running even without `--confirm`, like when the command picks
+    /// the current branch.
+    fn confirmation(&self) -> Option<(String, ConfirmDefault)> {
+        None
+    }
+
+    /// The merge policy rules the command breaks. They stop it unless overridden.
+    fn policy_violations(&self) -> Result<Vec<String>, String> {
+        Ok(vec![])
+    }
+
+    /// The branches the command updates, which stop it while they're frozen.
+    fn freeze_check(&self) -> Option<CheckFreeze> {
+        None
+    }
+
+    /// Clean up after a step of the run failed. Not called on dry runs.
+    fn after_failure(&self, _report: &Report) {}
+
+    fn parse_args_and_run_command<F>(&self, args: &ArgMatches, command: F) -> Result<(), RunError>
     where
         F: Fn(&Self) -> CommandChain,
     {
-        let from_step: usize = args
-            .value_of("from-step")
-            .and_then(|step| {
-                let step: Option<usize> = step.parse().ok();
-                step.map(|s| s + 1)
-            })
-            .unwrap_or(0);
+        self.run_command(&RunOptions::from_args(args), command)
+            .map(|_| ())
+    }
+
+    /// Run the chain built by `command`, with the journal, hooks, report and audit log, and
+    /// return the report. A failed step doesn't make this an error, the report says which step
+    /// failed.
+    fn run_command<F>(&self, options: &RunOptions, command: F) -> Result<Report, RunError>
+    where
+        F: Fn(&Self) -> CommandChain,
+    {
+        find_repo()?;
 
+        let from_step = options.from_step;
+        let dry_run = options.dry_run;
         let mut rerun_command = self.rerun_command();
-        let dry_run = args.is_present("dry-run");
+        let config = Config::load()?;
+        let autostash = options.autostash || config.get_bool("api-git.autostash").unwrap_or(false);
+        let restore_branch =
+            options.restore_branch || config.get_bool("api-git.restoreBranch").unwrap_or(false);
 
         if dry_run {
             rerun_command = format!("{cmd} --dry-run", cmd = rerun_command);
         }
 
+        if autostash {
+            rerun_command = format!("{cmd} --autostash", cmd = rerun_command);
+        }
+
+        if restore_branch {
+            rerun_command = format!("{cmd} --restore-branch", cmd = rerun_command);
+        }
+
         let step_runner = if dry_run {
             StepRunner::Dry
+        } else if config.get_str("api-git.backend").as_deref() == Some("native") {
+            StepRunner::Native
         } else {
             StepRunner::Run
         };
 
-        command(&self).run_and_print_from_step(from_step, &rerun_command, &step_runner);
+        // Resumed runs too, the branches can have been frozen since the first try
+        let freeze_override = match self.freeze_check() {
+            Some(check) => check.check(dry_run)?,
+            None => None,
+        };
+
+        // A resumed run was checked the first time, and its branches have been rebased since
+        let policy_override = if from_step == 0 {
+            check_policy(self.policy_violations()?, options, dry_run)?
+        } else {
+            None
+        };
+
+        let mut chain = command(self);
+
+        let command_name = rerun_command.split_whitespace().next().unwrap_or("");
+        let mut before_chain = CommandChain::new();
+        Hooks::for_command(command_name)?.add(
+            &mut before_chain,
+            HookPoint::BeforeChain,
+            None,
+            None,
+        );
+        chain.prepend(before_chain);
+
+        let journal = start_journal(from_step, &rerun_command)?;
+
+        if self.returns_to_start() || autostash {
+            chain.add(ReturnToStart::new(&journal));
+        }
+        if autostash {
+            chain.autostash();
+        }
+
+        // The plan and --from-step count the steps of the chain as it runs
+        if !dry_run {
+            let confirm_plan =
+                options.confirm || config.get_bool("api-git.confirm").unwrap_or(false);
+            let confirm_risky = !options.allow_destructive
+                && config.get_bool("api-git.confirmRisky").unwrap_or(false)
+                && chain
+                    .plan()
+                    .iter()
+                    .skip(from_step)
+                    .any(|(_, risk)| risk.is_risky());
+
+            // A resumed run was confirmed the first time, unless it has risky steps left
+            let question = if confirm_risky {
+                Some((
+                    "This runs destructive or deploy steps. Do you want to continue".to_string(),
+                    ConfirmDefault::No,
+                ))
+            } else if from_step > 0 {
+                None
+            } else {
+                self.confirmation().or_else(|| {
+                    confirm_plan.then(|| {
+                        (
+                            "Do you want to run these steps".to_string(),
+                            ConfirmDefault::No,
+                        )
+                    })
+                })
+            };
+
+            if let Some((question, default)) = question {
+                print_plan(&self.merges(), &chain, from_step);
+                if !confirm(&question, default)? {
+                    return Err(RunError::Declined);
+                }
+            }
+
+            journal.save();
+        }
+
+        let report = chain.run_and_print_from_step(from_step, &rerun_command, &step_runner);
+
+        report.print();
+        let mut entry = AuditEntry::from_report(&report, dry_run);
+        entry.policy_override = policy_override;
+        entry.freeze_override = freeze_override;
+        audit::record(&entry);
+        if let Some(path) = &options.report {
+            match std::fs::write(path, report.to_markdown()) {
+                Ok(()) => println!("Report written to {}", path.display()),
+                Err(err) => eprintln!("Failed to write the report to {}: {}", path.display(), err),
+            }
+        }
+
+        if let RunStepResult::Err = report.result {
+            if !dry_run {
+                self.after_failure(&report);
+            }
+
+            if restore_branch && !dry_run {
+                println!();
+                restore_after_failure(&journal);
+            }
+
+            if let Some(stash) = Journal::load().and_then(|journal| journal.autostash) {
+                println!();
+                println!(
+                    "{}",
+                    format!(
+                        "Your local changes are stashed in {} and will be restored when the command is resumed",
+                        stash
+                    )
+                    .yellow()
+                );
+            }
+        }
+
+        Ok(report)
+    }
+}
+
+/// The options every command takes.
+#[derive(Debug, Default)]
+pub struct RunOptions {
+    pub dry_run: bool,
+    /// The first step to run. `--from-step N` resumes after step N, so this is N + 1.
+    pub from_step: usize,
+    pub autostash: bool,
+    pub restore_branch: bool,
+    /// Show the plan and ask before running, also for commands that don't ask by default.
+    pub confirm: bool,
+    /// Run destructive and deploy steps without asking, even when `api-git.confirmRisky` is set.
+    pub allow_destructive: bool,
+    /// Run even though the merge policy is broken, for this reason.
+    pub override_policy: Option<String>,
+    /// Where to write the report as Markdown.
+    pub report: Option<PathBuf>,
+}
+
+impl RunOptions {
+    pub fn from_args(args: &ArgMatches) -> RunOptions {
+        let from_step: usize = args
+            .value_of("from-step")
+            .and_then(|step| {
+                let step: Option<usize> = step.parse().ok();
+                step.map(|s| s + 1)
+            })
+            .unwrap_or(0);
+
+        RunOptions {
+            dry_run: args.is_present("dry-run"),
+            from_step,
+            autostash: args.is_present("autostash"),
+            restore_branch: args.is_present("restore-branch"),
+            confirm: args.is_present("confirm"),
+            allow_destructive: args.is_present("allow-destructive"),
+            override_policy: args.value_of("override-policy").map(ToString::to_string),
+            report: args.value_of("report").map(PathBuf::from),
+        }
+    }
+}
+
+/// Stop if the merge policy is broken, unless it's a dry run or the policy is overridden. Returns
+/// the override to record in the audit log.
+fn check_policy(
+    violations: Vec<String>,
+    options: &RunOptions,
+    dry_run: bool,
+) -> Result<Option<PolicyOverride>, RunError> {
+    if violations.is_empty() {
+        return Ok(None);
+    }
+
+    eprintln!("{}", "This breaks the merge policy:".red());
+    for violation in &violations {
+        eprintln!("  {}", violation);
+    }
+    eprintln!();
+
+    match &options.override_policy {
+        Some(reason) => {
+            println!(
+                "{}",
+                format!("Overriding the merge policy: {}", reason).yellow()
+            );
+            println!();
+            Ok(Some(PolicyOverride {
+                reason: reason.clone(),
+                violations,
+            }))
+        }
+        None if dry_run => Ok(None),
+        None => {
+            audit::record(&AuditEntry::refused(violations));
+            Err(RunError::Invalid(
+            "Fix it, or pass --override-policy <REASON> to go ahead anyway. Overrides are recorded in the audit log".to_string(),
+            ))
+        }
+    }
+}
+
+/// Start a new journal, or pick up the one from the failed run that is being resumed. It's saved
+/// once the run goes ahead.
+/// The journal of a resumed run, or a new one. A new run is refused while the previous one still
+/// has local changes stashed, so its journal doesn't lose track of them.
+fn start_journal(from_step: usize, rerun_command: &str) -> Result<Journal, RunError> {
+    let previous = Journal::load();
+
+    if from_step > 0 {
+        if let Some(journal) = previous {
+            return Ok(journal);
+        }
+    }
+
+    let stash = previous.and_then(|journal| journal.autostash);
+    if let Some(index) = stash.as_deref().and_then(stash_index) {
+        return Err(RunError::Invalid(format!(
+            "A previous run left local changes stashed in stash@{{{index}}}. Restore them with `git stash pop stash@{{{index}}}` or drop them with `git stash drop stash@{{{index}}}` before starting a new run",
+            index = index
+        )));
+    }
+
+    Ok(Journal::new(rerun_command, head_branch(), head_commit()))
+}
+
+/// Quote an argument for the rerun command if it's empty or has whitespace.
+pub fn quote(value: &str) -> String {
+    if value.is_empty() || value.contains(char::is_whitespace) {
+        format!("'{}'", value.replace('\'', "'\\''"))
+    } else {
+        value.to_string()
     }
 }
@@ -1,25 +1,42 @@
 use crate::command_chain::*;
 use crate::commands::*;
-use crate::git::{current_branch_with_confirm, ConfirmDefault, Git};
+use crate::config::Config;
+use crate::deploys::RecordDeploy;
+use crate::freeze::CheckFreeze;
+use crate::git::{current_branch, find_repo, ConfirmDefault, Git};
+use crate::hooks::{HookPoint, Hooks};
+use crate::lock::{AcquireLock, ReleaseLock};
+use crate::merge_policy::MergePolicy;
+use crate::preview::PlannedMerge;
+use crate::report::{Report, StepStatus};
 use clap::ArgMatches;
+use colored::*;
 use std::fmt;
 
-pub fn run_on_remote(remote: Remote, args: &ArgMatches) {
-    OnRemote::from_args(&args, remote)
-        .unwrap()
-        .parse_args_and_run_command(&args, on_remote_command);
+pub fn run_on_remote(remote: Remote, args: &ArgMatches) -> Result<(), RunError> {
+    OnRemote::from_args(&args, remote)?.parse_args_and_run_command(&args, on_remote_command)
 }
 
-fn on_remote_command(args: &OnRemote) -> CommandChain {
+/// The steps of `on-staging` and `on-develop`. Doesn't read the repo or the terminal.
+pub fn on_remote_command(args: &OnRemote) -> CommandChain {
     let mut c = CommandChain::new();
+    let remote = args.remote.to_string();
+    let hooks = &args.hooks;
+    let (branch, target) = (Some(args.branch.as_str()), Some(remote.as_str()));
 
+    c.add(AcquireLock::new(&remote, &args.branch));
+
+    hooks.add(&mut c, HookPoint::BeforePush, None, branch);
     c.add(Git::push());
 
-    c.add(Git::checkout(&args.remote.to_string()));
+    c.add(Git::checkout(&remote));
 
     c.add(Git::pull());
 
+    hooks.add(&mut c, HookPoint::BeforeMerge, branch, target);
     c.add(Git::merge(&args.branch));
+    hooks.add(&mut c, HookPoint::AfterMerge, branch, target);
+    hooks.add(&mut c, HookPoint::BeforePush, None, target);
     c.add(Git::push());
 
     if !args.no_ship {
@@ -27,10 +44,14 @@ fn on_remote_command(args: &OnRemote) -> CommandChain {
             Remote::Staging => c.add(Git::push_staging()),
             Remote::Develop => c.add(Git::push_develop()),
         }
+        c.add(RecordDeploy::new(&remote, &args.branch));
+        hooks.add(&mut c, HookPoint::AfterDeploy, branch, target);
     }
 
     c.add(Git::checkout(&args.branch));
 
+    c.add(ReleaseLock::after_deploy(&remote));
+
     c
 }
 
@@ -50,35 +71,45 @@ impl fmt::Display for Remote {
 }
 
 #[derive(Debug)]
-struct OnRemote {
-    branch: String,
-    remote: Remote,
-    no_ship: bool,
+pub struct OnRemote {
+    pub branch: String,
+    pub remote: Remote,
+    pub no_ship: bool,
+    pub hooks: Hooks,
+    /// No branch was given so the current branch is merged, which is confirmed before running.
+    pub current_branch: bool,
+    /// Merge and deploy even if the environment is frozen, for this reason.
+    pub override_freeze: Option<String>,
 }
 
 impl OnRemote {
-    fn from_args(args: &ArgMatches, remote: Remote) -> Option<Self> {
-        let branch = if let Some(branch) = args.value_of("BRANCH") {
-            branch.to_string()
-        } else {
-            current_branch_with_confirm(
-                |current_branch| {
-                    format!(
-                        "Do you want to merge {} to {} remote",
-                        current_branch, remote,
-                    )
-                },
-                ConfirmDefault::Yes,
-            )
+    /// Merging `branch` into the remote's branch and deploying, with the hooks configured for the
+    /// repo in the current directory. Fails if its config is invalid.
+    pub fn for_repo(remote: Remote, branch: &str) -> Result<OnRemote, String> {
+        Ok(OnRemote {
+            hooks: Hooks::for_command(&format!("on-{}", remote))?,
+            branch: branch.to_string(),
+            remote,
+            no_ship: false,
+            current_branch: false,
+            override_freeze: None,
+        })
+    }
+
+    fn from_args(args: &ArgMatches, remote: Remote) -> Result<Self, String> {
+        find_repo()?;
+
+        let branch = match args.value_of("BRANCH") {
+            Some(branch) => branch.to_string(),
+            None => current_branch(),
         };
 
-        let no_ship = args.is_present("no-ship");
+        let mut s = OnRemote::for_repo(remote, &branch)?;
+        s.no_ship = args.is_present("no-ship");
+        s.current_branch = !args.is_present("BRANCH");
+        s.override_freeze = args.value_of("override-freeze").map(ToString::to_string);
 
-        Some(OnRemote {
-            branch,
-            remote,
-            no_ship,
-        })
+        Ok(s)
     }
 }
 
@@ -89,7 +120,125 @@ impl CommandArgs for OnRemote {
         if self.no_ship {
             rerun_command.push_str(" --no-ship");
         }
+        if let Some(reason) = &self.override_freeze {
+            rerun_command.push_str(&format!(" --override-freeze {}", quote(reason)));
+        }
         rerun_command.push_str(&format!(" {}", self.branch));
         rerun_command
     }
+
+    fn merges(&self) -> Vec<PlannedMerge> {
+        vec![PlannedMerge::new(
+            &self.branch,
+            &self.remote.to_string(),
+            false,
+        )]
+    }
+
+    fn policy_violations(&self) -> Result<Vec<String>, String> {
+        Ok(MergePolicy::from_config(&Config::load()?)?.violations(&self.merges()))
+    }
+
+    /// Nothing has been deployed if the run failed before the merge, so the lock is released
+    /// for others. After that it stays until the deploy is finished.
+    fn after_failure(&self, report: &Report) {
+        let remote = self.remote.to_string();
+        let position =
+            |command: String| report.steps.iter().position(|step| step.command == command);
+        let lock = position(AcquireLock::new(&remote, &self.branch).as_string());
+        let merge = position(Git::merge(&self.branch).as_string());
+        let failed = report
+            .steps
+            .iter()
+            .position(|step| step.status == StepStatus::Failed);
+
+        if let (Some(lock), Some(merge), Some(failed)) = (lock, merge, failed) {
+            if lock < failed && failed < merge {
+                println!();
+                let release = ReleaseLock::after_deploy(&remote);
+                println!(
+                    "{}",
+                    format!("-- Releasing: {}", release.as_string()).green()
+                );
+                release.run_step();
+                println!(
+                    "{}",
+                    format!(
+                        "Nothing was deployed, so the lock is released. Run `api-git {}` again instead of resuming, to take it again",
+                        self.rerun_command()
+                    )
+                    .yellow()
+                );
+            }
+        }
+    }
+
+    fn freeze_check(&self) -> Option<CheckFreeze> {
+        Some(CheckFreeze::new(
+            &[&self.remote.to_string()],
+            self.override_freeze.as_deref(),
+        ))
+    }
+
+    fn confirmation(&self) -> Option<(String, ConfirmDefault)> {
+        self.current_branch.then(|| {
+            (
+                format!(
+                    "Do you want to merge {} to {} remote",
+                    self.branch, self.remote
+                ),
+                ConfirmDefault::Yes,
+            )
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    #[allow(unused_imports)]
+    use super::*;
+
+    fn steps(remote: Remote, no_ship: bool) -> Vec<String> {
+        let args = OnRemote {
+            branch: "feature".to_string(),
+            hooks: Hooks::none(&format!("on-{}", remote)),
+            remote,
+            no_ship,
+            current_branch: false,
+            override_freeze: None,
+        };
+        on_remote_command(&args).record_from_step(0)
+    }
+
+    #[test]
+    fn test_on_remote_steps() {
+        assert_eq!(
+            steps(Remote::Staging, false),
+            vec![
+                "lock staging on origin for feature",
+                "git push",
+                "git checkout staging",
+                "git pull",
+                "git merge --no-edit feature",
+                "git push",
+                "git push staging staging:master",
+                "record the deploy of feature to staging",
+                "git checkout feature",
+                "release the lock on staging",
+            ]
+        );
+        assert_eq!(
+            steps(Remote::Develop, true),
+            vec![
+                "lock develop on origin for feature",
+                "git push",
+                "git checkout develop",
+                "git pull",
+                "git merge --no-edit feature",
+                "git push",
+                "git checkout feature",
+                "release the lock on develop",
+            ]
+        );
+    }
 }
@@ -0,0 +1,214 @@
+use crate::commands::*;
+use crate::config::Config;
+use crate::git::{head_branch, head_commit, Prompts};
+use crate::workflow::chain_from_steps;
+use clap::{App, ArgMatches};
+use git2::Repository;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process;
+
+/// Options of the core runner. They are taken out of the arguments before the rest is passed to
+/// the plugin. The bool is whether the option takes a value.
+const CORE_OPTIONS: &[(&str, bool)] = &[
+    ("--yes", false),
+    ("--no-input", false),
+    ("--dry-run", false),
+    ("--autostash", false),
+    ("--restore-branch", false),
+    ("--confirm", false),
+    ("--allow-destructive", false),
+    ("--from-step", true),
+    ("--report", true),
+];
+
+/// Run `api-git-<name>` from `PATH`.
+///
+/// The plugin gets the repo root in `API_GIT_REPO_ROOT`, the path to api-git in `API_GIT` and a
+/// JSON description of the repo, the resolved `api-git` config and the options in `API_GIT_ENV`.
+/// To have api-git run steps for it, the plugin writes a JSON array of steps, written like the
+/// ones in workflows, to the file in `API_GIT_STEPS`. They are run after the plugin exits, with
+/// the journal, dry run and resume of the built in commands. Resuming runs the plugin again.
+///
+/// `app` has the options of the core runner.
+pub fn run_plugin(name: &str, args: &ArgMatches, app: App) -> Result<(), RunError> {
+    let args: Vec<String> = args
+        .values_of("")
+        .map(|values| values.map(ToString::to_string).collect())
+        .unwrap_or_default();
+    let (core_args, plugin_args) = split_args(&args);
+
+    let executable = match find_plugin(name) {
+        Some(executable) => executable,
+        None => {
+            return Err(format!(
+                "No such command `{}`, and no api-git-{} in PATH",
+                name, name
+            )
+            .into())
+        }
+    };
+
+    let core_matches = app.get_matches_from(
+        Some(format!("api-git {}", name))
+            .into_iter()
+            .chain(core_args),
+    );
+    if core_matches.is_present("yes") {
+        Prompts::Yes.set();
+    } else if core_matches.is_present("no-input") {
+        Prompts::Defaults.set();
+    }
+
+    // Created empty, so nothing else can put steps there first
+    let steps_file = tempfile::Builder::new()
+        .prefix("api-git-steps-")
+        .suffix(".json")
+        .tempfile()
+        .map_err(|err| format!("Failed to create the steps file: {}", err))?;
+
+    let repo = Repository::open(".").ok();
+    let repo_root = repo
+        .as_ref()
+        .and_then(|repo| repo.workdir())
+        .map(|path| path.display().to_string())
+        .unwrap_or_default();
+
+    let config = match &repo {
+        Some(repo) => Config::for_repo(repo)?.entries_with_prefix("api-git"),
+        None => Default::default(),
+    };
+
+    let environment = serde_json::json!({
+        "version": env!("CARGO_PKG_VERSION"),
+        "repo_root": repo_root,
+        "branch": repo.as_ref().and_then(|_| head_branch()),
+        "head": repo.as_ref().and_then(|_| head_commit()),
+        "dry_run": core_matches.is_present("dry-run"),
+        "from_step": core_matches.value_of("from-step"),
+        "config": config,
+    });
+
+    let status = process::Command::new(&executable)
+        .args(&plugin_args)
+        .env("API_GIT", env::current_exe().unwrap_or_default())
+        .env("API_GIT_REPO_ROOT", &repo_root)
+        .env("API_GIT_ENV", environment.to_string())
+        .env("API_GIT_STEPS", steps_file.path())
+        .status()
+        .map_err(|err| format!("Failed to run {}: {}", executable.display(), err))?;
+
+    let steps = fs::read_to_string(steps_file.path())
+        .ok()
+        .filter(|steps| !steps.trim().is_empty());
+    drop(steps_file);
+
+    if !status.success() {
+        return Err(RunError::Plugin(status.code().unwrap_or(1)));
+    }
+
+    let steps: Vec<String> = match steps {
+        Some(steps) => serde_json::from_str(&steps)
+            .map_err(|err| format!("api-git-{} wrote invalid steps: {}", name, err))?,
+        None => return Ok(()),
+    };
+
+    if let Err(err) = chain_from_steps(&steps) {
+        return Err(format!("api-git-{} wrote invalid steps: {}", name, err).into());
+    }
+
+    PluginArgs {
+        name: name.to_string(),
+        args: plugin_args,
+    }
+    .parse_args_and_run_command(&core_matches, |_| {
+        chain_from_steps(&steps).expect("steps were checked")
+    })
+}
+
+#[derive(Debug)]
+struct PluginArgs {
+    name: String,
+    args: Vec<String>,
+}
+
+impl CommandArgs for PluginArgs {
+    fn rerun_command(&self) -> String {
+        let mut rerun_command = self.name.clone();
+        for arg in &self.args {
+            rerun_command.push_str(&format!(" {}", quote(arg)));
+        }
+        rerun_command
+    }
+}
+
+fn find_plugin(name: &str) -> Option<PathBuf> {
+    let file_name = format!("api-git-{}{}", name, env::consts::EXE_SUFFIX);
+    let path = env::var_os("PATH")?;
+
+    env::split_paths(&path)
+        .map(|dir| dir.join(&file_name))
+        .find(|candidate| is_executable(candidate))
+}
+
+#[cfg(unix)]
+fn is_executable(path: &std::path::Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+
+    fs::metadata(path)
+        .map(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0)
+        .unwrap_or(false)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &std::path::Path) -> bool {
+    path.is_file()
+}
+
+/// Split the arguments into the options for the core runner and the ones for the plugin.
+fn split_args(args: &[String]) -> (Vec<String>, Vec<String>) {
+    let mut core = vec![];
+    let mut plugin = vec![];
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        let name = arg.split('=').next().unwrap_or("");
+        match CORE_OPTIONS.iter().find(|(option, _)| *option == name) {
+            Some((_, takes_value)) => {
+                core.push(arg.clone());
+                if *takes_value && !arg.contains('=') {
+                    core.extend(args.next().cloned());
+                }
+            }
+            None => plugin.push(arg.clone()),
+        }
+    }
+
+    (core, plugin)
+}
+
+#[cfg(test)]
+mod test {
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[test]
+    fn test_split_args() {
+        let args = [
+            "deploy",
+            "--fast",
+            "--dry-run",
+            "--from-step",
+            "3",
+            "--report=r.md",
+        ]
+        .iter()
+        .map(|s| s.to_string())
+        .collect::<Vec<_>>();
+
+        let (core, plugin) = split_args(&args);
+        assert_eq!(core, vec!["--dry-run", "--from-step", "3", "--report=r.md"]);
+        assert_eq!(plugin, vec!["deploy", "--fast"]);
+    }
+}
@@ -0,0 +1,183 @@
+use crate::branch_delete::{ARCHIVE_PREFIX, REMOTE_ARCHIVE_PREFIX};
+use crate::command_chain::*;
+use crate::commands::*;
+use crate::git::{branch_exists, choose, find_repo, format_time, Git};
+use clap::ArgMatches;
+use colored::*;
+use git2::{Oid, Repository};
+
+pub fn run_restore_branch(args: &ArgMatches) -> Result<(), RunError> {
+    RestoreBranchArgs::from_args(args)?.parse_args_and_run_command(args, restore_branch_command)
+}
+
+fn restore_branch_command(args: &RestoreBranchArgs) -> CommandChain {
+    let mut c = CommandChain::new();
+
+    c.add(Git::from(format!("branch {} {}", args.branch, args.commit)));
+
+    if args.push {
+        c.add(Git::push_and_set_upstream(&args.branch));
+    }
+
+    c
+}
+
+#[derive(Debug)]
+struct RestoreBranchArgs {
+    branch: String,
+    commit: String,
+    push: bool,
+}
+
+impl RestoreBranchArgs {
+    fn from_args(args: &ArgMatches) -> Result<Self, RunError> {
+        let repo = find_repo()?;
+        let branch = args
+            .value_of("BRANCH")
+            .expect("BRANCH is required")
+            .to_string();
+
+        if branch_exists(&branch) {
+            return Err(format!("{} already exists", branch).into());
+        }
+
+        let commit = if let Some(commit) = args.value_of("commit") {
+            commit.to_string()
+        } else {
+            pick_candidate(&repo, &branch)?
+        };
+
+        Ok(RestoreBranchArgs {
+            branch,
+            commit,
+            push: args.is_present("push"),
+        })
+    }
+}
+
+impl CommandArgs for RestoreBranchArgs {
+    fn rerun_command(&self) -> String {
+        let mut rerun_command = String::new();
+        rerun_command.push_str("restore-branch");
+        if self.push {
+            rerun_command.push_str(" --push");
+        }
+        rerun_command.push_str(&format!(" --commit {}", self.commit));
+        rerun_command.push_str(&format!(" {}", self.branch));
+        rerun_command
+    }
+}
+
+/// A commit the branch used to point at.
+struct Candidate {
+    oid: Oid,
+    source: &'static str,
+    time: git2::Time,
+}
+
+fn pick_candidate(repo: &Repository, branch: &str) -> Result<String, RunError> {
+    let candidates = find_candidates(repo, branch);
+
+    if candidates.is_empty() {
+        return Err(format!(
+            "Couldn't find {} in the archive, the reflog or origin's branches",
+            branch
+        )
+        .into());
+    }
+
+    println!("Found these tips of {}:", branch);
+    println!();
+    for (idx, candidate) in candidates.iter().enumerate() {
+        let commit = repo
+            .find_commit(candidate.oid)
+            .expect("failed to find commit");
+        println!(
+            "  {} {} {} {} ({})",
+            format!("{}.", idx + 1).bold(),
+            candidate.oid.to_string()[..8].yellow(),
+            format_time(candidate.time),
+            commit.summary().unwrap_or(""),
+            candidate.source,
+        );
+    }
+    println!();
+
+    match choose(
+        &format!("Which one should {} be restored at", branch),
+        candidates.len(),
+    )? {
+        Some(idx) => Ok(candidates[idx].oid.to_string()),
+        None => {
+            println!("Run again with --commit <SHA> to restore another commit");
+            Err(RunError::Declined)
+        }
+    }
+}
+
+/// Look for the tips of a deleted branch, in the order they should be preferred.
+fn find_candidates(repo: &Repository, branch: &str) -> Vec<Candidate> {
+    let mut candidates = vec![];
+
+    for (prefix, source) in &[
+        (ARCHIVE_PREFIX, "archived by api-git"),
+        (REMOTE_ARCHIVE_PREFIX, "archived from origin by api-git"),
+    ] {
+        if let Ok(oid) = repo.refname_to_id(&format!("{}{}", prefix, branch)) {
+            if let Ok(commit) = repo.find_commit(oid) {
+                candidates.push(Candidate {
+                    oid,
+                    source,
+                    time: commit.time(),
+                });
+            }
+        }
+    }
+
+    // Deleting a branch also deletes its reflog, but HEAD's reflog remembers where the branch
+    // was every time we switched away from it
+    let moving_from = format!("checkout: moving from {} to ", branch);
+    if let Ok(reflog) = repo.reflog("HEAD") {
+        for entry in reflog.iter() {
+            if entry.message().is_some_and(|m| m.starts_with(&moving_from)) {
+                candidates.push(Candidate {
+                    oid: entry.id_old(),
+                    source: "local reflog",
+                    time: entry.committer().when(),
+                });
+            }
+        }
+    }
+
+    // Someone else may have deleted it on origin, which we only see once we prune
+    let remote_branch = format!("refs/remotes/origin/{}", branch);
+    if let Ok(oid) = repo.refname_to_id(&remote_branch) {
+        if let Ok(commit) = repo.find_commit(oid) {
+            candidates.push(Candidate {
+                oid,
+                source: "origin's branch",
+                time: commit.time(),
+            });
+        }
+    }
+
+    // Earlier tips it was fetched at
+    if let Ok(reflog) = repo.reflog(&remote_branch) {
+        for entry in reflog.iter() {
+            candidates.push(Candidate {
+                oid: entry.id_new(),
+                source: "origin's reflog",
+                time: entry.committer().when(),
+            });
+        }
+    }
+
+    let mut seen = std::collections::HashSet::new();
+    candidates.retain(|candidate| {
+        !candidate.oid.is_zero()
+            && repo.find_commit(candidate.oid).is_ok()
+            && seen.insert(candidate.oid)
+    });
+
+    candidates
+}
@@ -1,39 +1,55 @@
 use crate::command_chain::*;
 use crate::commands::*;
-use crate::git::Git;
+use crate::freeze::CheckFreeze;
+use crate::git::{find_repo, Git};
+use crate::hooks::{HookPoint, Hooks};
+use crate::preview::PlannedMerge;
+use crate::propagation::{propagation_edges, Edge};
 use clap::ArgMatches;
 
-pub fn run_ship_hotfix(args: &ArgMatches) {
-    ShipHotfixArgs::from_args(&args)
-        .unwrap()
-        .parse_args_and_run_command(&args, ship_hotfix_command);
+pub fn run_ship_hotfix(args: &ArgMatches) -> Result<(), RunError> {
+    ShipHotfixArgs::from_args(&args)?.parse_args_and_run_command(&args, ship_hotfix_command)
 }
 
-fn ship_hotfix_command(_args: &ShipHotfixArgs) -> CommandChain {
+/// The steps of `ship-hotfix`. Doesn't read the repo or the terminal.
+pub fn ship_hotfix_command(args: &ShipHotfixArgs) -> CommandChain {
     let mut c = CommandChain::new();
+    let hooks = &args.hooks;
 
     c.add(Git::checkout("master"));
     c.add(Git::pull_rebase());
+    hooks.add(&mut c, HookPoint::BeforePush, None, Some("master"));
     c.add(Git::push());
 
-    for branch in ["staging", "develop"].iter() {
-        c.add(Git::checkout(branch));
-        c.add(Git::pull());
-        c.add(Git::merge("master"));
-        c.add(Git::push());
+    for edge in &args.edges {
+        c.unit(&edge.to_string(), &edge.after(&args.edges), |c| {
+            edge.add_steps(c, hooks);
+            hooks.add(c, HookPoint::BeforePush, None, Some(&edge.into));
+            c.add(Git::push());
+        });
     }
 
-    c.add(Git::checkout("master"));
-
     c
 }
 
 #[derive(Debug)]
-struct ShipHotfixArgs;
+pub struct ShipHotfixArgs {
+    /// The downstream merges after master has been updated.
+    pub edges: Vec<Edge>,
+    pub hooks: Hooks,
+    /// Ship even if one of the branches is frozen, for this reason.
+    pub override_freeze: Option<String>,
+}
 
 impl ShipHotfixArgs {
-    fn from_args(_args: &ArgMatches) -> Option<Self> {
-        Some(ShipHotfixArgs)
+    fn from_args(args: &ArgMatches) -> Result<Self, String> {
+        find_repo()?;
+
+        Ok(ShipHotfixArgs {
+            edges: propagation_edges("master")?,
+            hooks: Hooks::for_command("ship-hotfix")?,
+            override_freeze: args.value_of("override-freeze").map(ToString::to_string),
+        })
     }
 }
 
@@ -41,6 +57,54 @@ impl CommandArgs for ShipHotfixArgs {
     fn rerun_command(&self) -> String {
         let mut rerun_command = String::new();
         rerun_command.push_str("ship-hotfix");
+        if let Some(reason) = &self.override_freeze {
+            rerun_command.push_str(&format!(" --override-freeze {}", quote(reason)));
+        }
         rerun_command
     }
+
+    fn freeze_check(&self) -> Option<CheckFreeze> {
+        let mut targets = vec!["master"];
+        targets.extend(self.edges.iter().map(|edge| edge.into.as_str()));
+        Some(CheckFreeze::new(&targets, self.override_freeze.as_deref()))
+    }
+
+    fn merges(&self) -> Vec<PlannedMerge> {
+        self.edges
+            .iter()
+            .map(|edge| PlannedMerge::new(&edge.from, &edge.into, false))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    #[allow(unused_imports)]
+    use super::*;
+    use crate::propagation::PropagationGraph;
+
+    #[test]
+    fn test_ship_hotfix_steps() {
+        let args = ShipHotfixArgs {
+            edges: PropagationGraph::parse(&["master -> staging".to_string()])
+                .unwrap()
+                .edges_from("master")
+                .unwrap(),
+            hooks: Hooks::none("ship-hotfix"),
+            override_freeze: None,
+        };
+
+        assert_eq!(
+            ship_hotfix_command(&args).record_from_step(0),
+            vec![
+                "git checkout master",
+                "git pull --rebase",
+                "git push",
+                "fast-forward staging to origin/staging",
+                "git checkout staging",
+                "git merge --no-edit master",
+                "git push",
+            ]
+        );
+    }
 }
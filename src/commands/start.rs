@@ -1,15 +1,16 @@
+use crate::branch_policy::{slugify, BranchPolicy, TemplateVars};
 use crate::command_chain::*;
 use crate::commands::*;
-use crate::git::Git;
+use crate::config::Config;
+use crate::git::{find_repo, Git};
 use clap::ArgMatches;
 
-pub fn run_start(args: &ArgMatches) {
-    StartArgs::from_args(&args)
-        .unwrap()
-        .parse_args_and_run_command(&args, start_command);
+pub fn run_start(args: &ArgMatches) -> Result<(), RunError> {
+    StartArgs::from_args(&args)?.parse_args_and_run_command(&args, start_command)
 }
 
-fn start_command(args: &StartArgs) -> CommandChain {
+/// The steps of `start`. Doesn't read the repo or the terminal.
+pub fn start_command(args: &StartArgs) -> CommandChain {
     let mut c = CommandChain::new();
 
     c.add(Git::checkout(&args.base));
@@ -25,20 +26,20 @@ fn start_command(args: &StartArgs) -> CommandChain {
 }
 
 #[derive(Debug)]
-struct StartArgs {
-    branch: String,
-    prefix: bool,
-    push: bool,
-    base: String,
+pub struct StartArgs {
+    pub branch: String,
+    pub push: bool,
+    pub base: String,
 }
 
 impl StartArgs {
-    fn from_args(args: &ArgMatches) -> Option<Self> {
-        let branch = if let Some(branch) = args.value_of("BRANCH") {
-            branch.into()
-        } else {
-            return None;
-        };
+    fn from_args(args: &ArgMatches) -> Result<Self, String> {
+        find_repo()?;
+
+        let branch = args
+            .value_of("BRANCH")
+            .ok_or_else(|| "Give the name of the branch to start".to_string())?
+            .to_string();
 
         let base = if let Some(base) = args.value_of("base") {
             base.into()
@@ -48,13 +49,11 @@ impl StartArgs {
 
         let mut s = StartArgs {
             branch,
-            prefix: false,
             push: false,
             base,
         };
 
         if args.is_present("prefix") {
-            s.prefix = true;
             s.branch = format!("{}-{}", s.base, s.branch);
         }
 
@@ -62,17 +61,40 @@ impl StartArgs {
             s.push = true;
         }
 
-        Some(s)
+        let config = Config::load()?;
+        let vars = TemplateVars {
+            user: template_user(&config),
+            ticket: args.value_of("ticket").map(ToString::to_string),
+            base: s.base.clone(),
+        };
+
+        match BranchPolicy::from_config(&config)?.apply(&s.branch, &vars) {
+            Ok(branch) => s.branch = branch,
+            Err(violations) => {
+                let mut message = "Invalid branch name:".to_string();
+                for violation in violations {
+                    message.push_str(&format!("\n  {}", violation));
+                }
+                return Err(message);
+            }
+        }
+
+        Ok(s)
     }
 }
 
+fn template_user(config: &Config) -> String {
+    config
+        .get_str("api-git.user")
+        .or_else(|| config.get_str("user.name").map(|name| slugify(&name)))
+        .or_else(|| std::env::var("USER").ok())
+        .unwrap_or_default()
+}
+
 impl CommandArgs for StartArgs {
     fn rerun_command(&self) -> String {
         let mut rerun_command = String::new();
         rerun_command.push_str("start");
-        if self.prefix {
-            rerun_command.push_str(" --prefix");
-        }
         if self.push {
             rerun_command.push_str(" --push");
         }
@@ -80,4 +102,39 @@ impl CommandArgs for StartArgs {
         rerun_command.push_str(&format!(" {}", self.branch));
         rerun_command
     }
+
+    fn returns_to_start(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod test {
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[test]
+    fn test_start_steps() {
+        let mut args = StartArgs {
+            branch: "feature".to_string(),
+            push: false,
+            base: "develop".to_string(),
+        };
+
+        assert_eq!(
+            start_command(&args).record_from_step(0),
+            vec![
+                "git checkout develop",
+                "git pull",
+                "git branch feature",
+                "git checkout feature",
+            ]
+        );
+
+        args.push = true;
+        assert_eq!(
+            start_command(&args).record_from_step(0).last().unwrap(),
+            "git push --set-upstream origin feature"
+        );
+    }
 }
@@ -0,0 +1,88 @@
+use crate::commands::*;
+use crate::workflow::Workflow;
+use clap::{App, Arg, ArgMatches, SubCommand};
+use std::collections::HashMap;
+
+pub fn subcommand<'a>(workflow: &'a Workflow) -> App<'a, 'a> {
+    let mut subcommand = SubCommand::with_name(&workflow.name).about(
+        workflow
+            .about
+            .as_deref()
+            .unwrap_or("Workflow defined in the config"),
+    );
+
+    for arg in &workflow.args {
+        subcommand = subcommand.arg(Arg::with_name(arg).required(true));
+    }
+
+    for flag in &workflow.flags {
+        let mut arg = Arg::with_name(&flag.name)
+            .long(&flag.name)
+            .takes_value(true)
+            .required(flag.default.is_none());
+        if let Some(default) = &flag.default {
+            arg = arg.default_value(default);
+        }
+        subcommand = subcommand.arg(arg);
+    }
+
+    subcommand
+}
+
+pub fn run_workflow(workflow: &Workflow, args: &ArgMatches) -> Result<(), RunError> {
+    WorkflowArgs::from_args(workflow, args)
+        .parse_args_and_run_command(args, |args| workflow.chain(&args.values))
+}
+
+#[derive(Debug)]
+struct WorkflowArgs {
+    name: String,
+    args: Vec<String>,
+    flags: Vec<(String, String)>,
+    values: HashMap<String, String>,
+}
+
+impl WorkflowArgs {
+    fn from_args(workflow: &Workflow, args: &ArgMatches) -> Self {
+        let value = |name: &str| args.value_of(name).unwrap_or("").to_string();
+
+        let positional = workflow
+            .args
+            .iter()
+            .map(|arg| value(arg))
+            .collect::<Vec<_>>();
+        let flags = workflow
+            .flags
+            .iter()
+            .map(|flag| (flag.name.clone(), value(&flag.name)))
+            .collect::<Vec<_>>();
+
+        let values = workflow
+            .args
+            .iter()
+            .cloned()
+            .zip(positional.iter().cloned())
+            .chain(flags.iter().cloned())
+            .collect();
+
+        WorkflowArgs {
+            name: workflow.name.clone(),
+            args: positional,
+            flags,
+            values,
+        }
+    }
+}
+
+impl CommandArgs for WorkflowArgs {
+    fn rerun_command(&self) -> String {
+        let mut rerun_command = self.name.clone();
+        for (name, value) in &self.flags {
+            rerun_command.push_str(&format!(" --{} {}", name, quote(value)));
+        }
+        for arg in &self.args {
+            rerun_command.push_str(&format!(" {}", quote(arg)));
+        }
+        rerun_command
+    }
+}
@@ -0,0 +1,187 @@
+use git2::Repository;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// Name of the file, in the root of the repo, that teams can commit to share settings.
+pub const SHARED_CONFIG_FILE: &str = ".api-git";
+
+/// Settings for api-git.
+///
+/// Values are read from the regular git config first (so `git config api-git.foo bar` works), and
+/// then from the `.api-git` file committed at the root of the repo, which uses the same format.
+pub struct Config {
+    layers: Vec<git2::Config>,
+}
+
+impl Config {
+    /// Settings of the repo in the current directory. Fails if the `.api-git` file can't be
+    /// parsed.
+    pub fn load() -> Result<Config, String> {
+        let repo = Repository::open(".").expect("failed to open repo in current directory");
+        Config::for_repo(&repo)
+    }
+
+    pub fn for_repo(repo: &Repository) -> Result<Config, String> {
+        let mut layers = vec![];
+
+        if let Ok(mut config) = repo.config() {
+            layers.push(config.snapshot().expect("failed to read git config"));
+        }
+
+        if let Some(workdir) = repo.workdir() {
+            let path = workdir.join(SHARED_CONFIG_FILE);
+            if path.exists() {
+                layers.push(open_file(&path)?);
+            }
+        }
+
+        Ok(Config { layers })
+    }
+
+    pub fn get_str(&self, key: &str) -> Option<String> {
+        self.layers
+            .iter()
+            .filter_map(|config| config.get_string(key).ok())
+            .next()
+    }
+
+    pub fn get_bool(&self, key: &str) -> Option<bool> {
+        self.layers
+            .iter()
+            .filter_map(|config| config.get_bool(key).ok())
+            .next()
+    }
+
+    pub fn get_i64(&self, key: &str) -> Option<i64> {
+        self.layers
+            .iter()
+            .filter_map(|config| config.get_i64(key).ok())
+            .next()
+    }
+
+    /// Get a number of things, which can't be negative.
+    pub fn get_count(&self, key: &str) -> Result<Option<usize>, String> {
+        match self.get_i64(key) {
+            Some(value) if value < 0 => Err(format!("Invalid {}: {} is negative", key, value)),
+            value => Ok(value.map(|value| value as usize)),
+        }
+    }
+
+    /// Get all the values of a key that may be given multiple times.
+    ///
+    /// Values from the first layer that has the key win, so a local setting replaces the shared
+    /// list rather than being appended to it.
+    pub fn get_all(&self, key: &str) -> Vec<String> {
+        self.layers
+            .iter()
+            .map(|config| values_of(config, key))
+            .find(|values| !values.is_empty())
+            .unwrap_or_default()
+    }
+
+    /// All values whose key starts with `prefix`, with the same precedence as `get_all`.
+    pub fn entries_with_prefix(&self, prefix: &str) -> BTreeMap<String, Vec<String>> {
+        let mut resolved = BTreeMap::new();
+
+        for config in &self.layers {
+            let mut layer: BTreeMap<String, Vec<String>> = BTreeMap::new();
+            let entries = match config.entries(None) {
+                Ok(entries) => entries,
+                Err(_) => continue,
+            };
+
+            for entry in &entries {
+                let entry = entry.expect("failed to read config entry");
+                if let (Some(name), Some(value)) = (entry.name(), entry.value()) {
+                    if name.starts_with(prefix) {
+                        layer
+                            .entry(name.to_string())
+                            .or_default()
+                            .push(value.to_string());
+                    }
+                }
+            }
+
+            for (name, values) in layer {
+                resolved.entry(name).or_insert(values);
+            }
+        }
+
+        resolved
+    }
+
+    /// Names of the subsections of `section` that have a `variable`, like the `x` in
+    /// `[section "x"]`, in the order they first appear.
+    pub fn subsections(&self, section: &str, variable: &str) -> Vec<String> {
+        let prefix = format!("{}.", section.to_lowercase());
+        let suffix = format!(".{}", variable.to_lowercase());
+        let mut names: Vec<String> = vec![];
+
+        for config in &self.layers {
+            let entries = match config.entries(None) {
+                Ok(entries) => entries,
+                Err(_) => continue,
+            };
+
+            for entry in &entries {
+                let entry = entry.expect("failed to read config entry");
+                let name = match entry.name() {
+                    Some(name) => name,
+                    None => continue,
+                };
+
+                if name.len() > prefix.len() + suffix.len()
+                    && name.starts_with(&prefix)
+                    && name.ends_with(&suffix)
+                {
+                    let subsection = &name[prefix.len()..name.len() - suffix.len()];
+                    if !names.iter().any(|n| n == subsection) {
+                        names.push(subsection.to_string());
+                    }
+                }
+            }
+        }
+
+        names
+    }
+}
+
+fn open_file(path: &Path) -> Result<git2::Config, String> {
+    git2::Config::open(path)
+        .and_then(|mut config| config.snapshot())
+        .map_err(|err| format!("Invalid {}: {}", path.display(), err.message()))
+}
+
+fn values_of(config: &git2::Config, key: &str) -> Vec<String> {
+    let key = normalize_key(key);
+    let mut values = vec![];
+
+    let entries = match config.entries(None) {
+        Ok(entries) => entries,
+        Err(_) => return values,
+    };
+
+    for entry in &entries {
+        let entry = entry.expect("failed to read config entry");
+        if entry.name() == Some(key.as_str()) {
+            if let Some(value) = entry.value() {
+                values.push(value.to_string());
+            }
+        }
+    }
+
+    values
+}
+
+/// Section and variable names are case insensitive, subsection names are not.
+fn normalize_key(key: &str) -> String {
+    let first_dot = key.find('.').unwrap_or(0);
+    let last_dot = key.rfind('.').unwrap_or(0);
+
+    format!(
+        "{}{}{}",
+        key[..first_dot].to_lowercase(),
+        &key[first_dot..last_dot],
+        key[last_dot..].to_lowercase(),
+    )
+}
@@ -0,0 +1,228 @@
+use crate::command_chain::{Risk, RunStepResult, Step};
+use crate::git::open_repo;
+use crate::remote_refs;
+use chrono::{DateTime, FixedOffset};
+use colored::*;
+use git2::{Oid, Repository};
+use std::process;
+
+/// The notes with the deploys of each commit, locally and on origin.
+pub const NOTES_REF: &str = "refs/notes/api-git/deploys";
+
+/// Origin's notes, as fetched before merging them into `NOTES_REF`.
+const ORIGIN_NOTES_REF: &str = "refs/notes/api-git-origin/deploys";
+
+/// How many times pushing the notes is retried when someone else pushed theirs first.
+const PUSH_ATTEMPTS: usize = 3;
+
+/// A deploy of a commit to an environment, as recorded in a line of its note.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Deploy {
+    pub commit: Oid,
+    pub env: String,
+    /// The branch that was merged and deployed.
+    pub branch: String,
+    pub user: String,
+    pub time: DateTime<FixedOffset>,
+}
+
+impl Deploy {
+    /// The time goes first, so the lines stay in order when notes are merged with
+    /// `cat_sort_uniq`, which sorts them.
+    fn to_line(&self) -> String {
+        format!(
+            "{} {} {} {}\n",
+            self.time
+                .to_rfc3339_opts(chrono::SecondsFormat::Millis, false),
+            self.env,
+            self.branch,
+            self.user
+        )
+    }
+}
+
+/// The deploys in a note. A commit deployed more than once has one line per deploy.
+fn parse_note(commit: Oid, note: &str) -> Vec<Deploy> {
+    note.lines()
+        .filter_map(|line| {
+            let mut fields = line.trim().splitn(4, ' ');
+
+            Some(Deploy {
+                time: DateTime::parse_from_rfc3339(fields.next()?).ok()?,
+                commit,
+                env: fields.next()?.to_string(),
+                branch: fields.next()?.to_string(),
+                user: fields.next().unwrap_or("").to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Fetch origin's deploy notes and merge them into the local ones. The local notes are never
+/// overwritten, so deploys that haven't been pushed yet are kept.
+pub fn fetch_deploys() {
+    if !remote_refs::fetch_into(
+        "refs/notes/api-git/",
+        "refs/notes/api-git-origin/",
+        "deploy notes",
+    ) {
+        return;
+    }
+
+    let has_origin_notes = open_repo().refname_to_id(ORIGIN_NOTES_REF).is_ok();
+    let merged = !has_origin_notes
+        || process::Command::new("git")
+            .args([
+                "notes",
+                "--ref",
+                NOTES_REF,
+                "merge",
+                "--quiet",
+                "--strategy=cat_sort_uniq",
+                ORIGIN_NOTES_REF,
+            ])
+            .status()
+            .is_ok_and(|status| status.success());
+
+    if !merged {
+        eprintln!(
+            "{}",
+            "Couldn't merge the deploy notes from origin, using the local ones".yellow()
+        );
+    }
+}
+
+/// The deploys to `env`, oldest first, from the local deploy notes.
+pub fn deploys(repo: &Repository, env: &str) -> Vec<Deploy> {
+    let notes = match repo.notes(Some(NOTES_REF)) {
+        Ok(notes) => notes,
+        Err(_) => return vec![],
+    };
+
+    let mut deploys = notes
+        .filter_map(Result::ok)
+        .filter_map(|(_, commit)| {
+            let note = repo.find_note(Some(NOTES_REF), commit).ok()?;
+            Some(parse_note(commit, note.message()?))
+        })
+        .flat_map(|deploys| deploys.into_iter().enumerate())
+        .filter(|(_, deploy)| deploy.env == env)
+        .collect::<Vec<_>>();
+    // Deploys at the same time are ordered by their line in the note, then by commit
+    deploys.sort_by(|(a_line, a), (b_line, b)| {
+        (a.time, a_line, a.commit).cmp(&(b.time, b_line, b.commit))
+    });
+    deploys.into_iter().map(|(_, deploy)| deploy).collect()
+}
+
+/// Add a note to the tip of the environment's branch saying it was deployed, and push the notes
+/// to origin. The deploy has already happened, so this only warns if it fails.
+pub struct RecordDeploy {
+    env: String,
+    branch: String,
+}
+
+impl RecordDeploy {
+    pub fn new(env: &str, branch: &str) -> RecordDeploy {
+        RecordDeploy {
+            env: env.to_string(),
+            branch: branch.to_string(),
+        }
+    }
+
+    fn add_note(&self, repo: &Repository) -> Result<(), git2::Error> {
+        let commit = repo
+            .revparse_single(&format!("refs/heads/{}", self.env))?
+            .id();
+        let signature = repo.signature()?;
+
+        let deploy = Deploy {
+            commit,
+            env: self.env.clone(),
+            branch: self.branch.clone(),
+            user: format!(
+                "{} <{}>",
+                signature.name().unwrap_or(""),
+                signature.email().unwrap_or("")
+            ),
+            time: chrono::Local::now().fixed_offset(),
+        };
+
+        let note = match repo.find_note(Some(NOTES_REF), commit) {
+            Ok(note) => format!("{}{}", note.message().unwrap_or(""), deploy.to_line()),
+            Err(_) => deploy.to_line(),
+        };
+        repo.note(&signature, &signature, Some(NOTES_REF), commit, &note, true)?;
+        Ok(())
+    }
+}
+
+impl Step for RecordDeploy {
+    fn run_step(&self) -> RunStepResult {
+        // Start from origin's notes, so adding to a note doesn't lose what others added
+        fetch_deploys();
+
+        if let Err(err) = self.add_note(&open_repo()) {
+            eprintln!(
+                "{}",
+                format!("Failed to record the deploy: {}", err.message()).yellow()
+            );
+            return RunStepResult::Ok;
+        }
+
+        for attempt in 0..PUSH_ATTEMPTS {
+            // Someone else pushed first, take in their notes so the push is a fast-forward
+            if attempt > 0 {
+                fetch_deploys();
+            }
+
+            let pushed = process::Command::new("git")
+                .args(["push", "--quiet", "origin", NOTES_REF])
+                .status()
+                .is_ok_and(|status| status.success());
+            if pushed {
+                return RunStepResult::Ok;
+            }
+        }
+
+        eprintln!(
+            "{}",
+            "Failed to push the deploy notes to origin, the deploy is only recorded locally"
+                .yellow()
+        );
+        RunStepResult::Ok
+    }
+
+    fn as_string(&self) -> String {
+        format!("record the deploy of {} to {}", self.branch, self.env)
+    }
+
+    fn risk(&self) -> Risk {
+        Risk::RemoteWrite
+    }
+}
+
+#[cfg(test)]
+mod test {
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[test]
+    fn test_parse_note() {
+        let commit = Oid::zero();
+        let deploy = |branch: &str, time: &str| Deploy {
+            commit,
+            env: "staging".to_string(),
+            branch: branch.to_string(),
+            user: "Alice <alice@example.com>".to_string(),
+            time: DateTime::parse_from_rfc3339(time).unwrap(),
+        };
+
+        let first = deploy("feature", "2026-10-19T12:00:00+02:00");
+        let second = deploy("fix", "2026-10-20T09:30:00+02:00");
+        let note = format!("{}{}", first.to_line(), second.to_line());
+
+        assert_eq!(parse_note(commit, &note), vec![first, second]);
+        assert_eq!(parse_note(commit, "not a deploy"), vec![]);
+    }
+}
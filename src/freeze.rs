@@ -0,0 +1,206 @@
+use crate::command_chain::{Risk, RunStepResult, Step};
+use crate::git::{format_time, open_repo};
+use crate::remote_refs::{self, RemoteRef};
+use colored::*;
+use git2::Repository;
+
+/// Where freezes are stored, locally and on origin. Each frozen branch has a ref whose message
+/// has the reason.
+pub const FREEZE_PREFIX: &str = "refs/api-git/freezes/";
+
+/// A branch nothing can be merged into or deployed from.
+pub struct Freeze {
+    pub branch: String,
+    pub author: String,
+    pub time: git2::Time,
+    pub reason: String,
+}
+
+impl Freeze {
+    fn from_ref(remote_ref: RemoteRef) -> Freeze {
+        Freeze {
+            branch: remote_ref.name[FREEZE_PREFIX.len()..].to_string(),
+            reason: remote_ref.body().to_string(),
+            author: remote_ref.author,
+            time: remote_ref.time,
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        format!(
+            "{} is frozen since {} by {}: {}",
+            self.branch,
+            format_time(self.time),
+            self.author,
+            self.reason
+        )
+    }
+}
+
+/// Update the local freeze refs from origin.
+pub fn fetch_freezes() {
+    remote_refs::fetch(FREEZE_PREFIX, "freezes");
+}
+
+/// The freeze of a branch, from the local freeze refs.
+pub fn find_freeze(repo: &Repository, branch: &str) -> Option<Freeze> {
+    remote_refs::find(repo, &format!("{}{}", FREEZE_PREFIX, branch)).map(Freeze::from_ref)
+}
+
+/// Every freeze, from the local freeze refs.
+pub fn all_freezes(repo: &Repository) -> Vec<Freeze> {
+    remote_refs::find_all(repo, FREEZE_PREFIX)
+        .into_iter()
+        .map(Freeze::from_ref)
+        .collect()
+}
+
+/// Stop a command if one of the branches it updates is frozen on origin, unless the freeze is
+/// overridden. Checked before the chain runs, resumed runs included.
+#[derive(Debug)]
+pub struct CheckFreeze {
+    branches: Vec<String>,
+    override_reason: Option<String>,
+}
+
+impl CheckFreeze {
+    pub fn new(branches: &[&str], override_reason: Option<&str>) -> CheckFreeze {
+        CheckFreeze {
+            branches: branches.iter().map(ToString::to_string).collect(),
+            override_reason: override_reason.map(ToString::to_string),
+        }
+    }
+
+    /// Fails if a branch is frozen and it's neither overridden nor a dry run. Returns the reason
+    /// when a freeze was overridden, to record in the audit log.
+    pub fn check(&self, dry_run: bool) -> Result<Option<String>, String> {
+        fetch_freezes();
+        let repo = open_repo();
+
+        let freezes = self
+            .branches
+            .iter()
+            .filter_map(|branch| find_freeze(&repo, branch))
+            .collect::<Vec<_>>();
+
+        if freezes.is_empty() {
+            return Ok(None);
+        }
+
+        for freeze in &freezes {
+            println!("{}", freeze.describe().red());
+        }
+
+        match &self.override_reason {
+            Some(reason) => {
+                println!("{}", format!("Overriding the freeze: {}", reason).yellow());
+                println!();
+                Ok(Some(reason.clone()))
+            }
+            None if dry_run => Ok(None),
+            None => {
+                Err("Wait for the freeze to end, or pass --override-freeze <REASON>".to_string())
+            }
+        }
+    }
+}
+
+/// Freeze a branch on origin. Fails if it's already frozen.
+pub struct StartFreeze {
+    branch: String,
+    reason: String,
+}
+
+impl StartFreeze {
+    pub fn new(branch: &str, reason: &str) -> StartFreeze {
+        StartFreeze {
+            branch: branch.to_string(),
+            reason: reason.to_string(),
+        }
+    }
+}
+
+impl Step for StartFreeze {
+    fn run_step(&self) -> RunStepResult {
+        fetch_freezes();
+        let repo = open_repo();
+
+        if let Some(freeze) = find_freeze(&repo, &self.branch) {
+            eprintln!("{}", freeze.describe().red());
+            return RunStepResult::Err;
+        }
+
+        let name = format!("{}{}", FREEZE_PREFIX, self.branch);
+        let message = format!("Freeze {}\n\n{}\n", self.branch, self.reason);
+        match remote_refs::create_commit(&repo, &message) {
+            // Only if no one else froze it since the fetch
+            Ok(oid) if remote_refs::compare_and_swap(&repo, &name, None, Some(oid)) => {
+                RunStepResult::Ok
+            }
+            Ok(_) => {
+                eprintln!("{}", format!("Failed to push {} to origin", name).red());
+                RunStepResult::Err
+            }
+            Err(err) => {
+                eprintln!("{}", err.message().red());
+                RunStepResult::Err
+            }
+        }
+    }
+
+    fn as_string(&self) -> String {
+        format!("freeze {} on origin: {}", self.branch, self.reason)
+    }
+
+    fn risk(&self) -> Risk {
+        Risk::RemoteWrite
+    }
+}
+
+/// End the freeze of a branch on origin. A branch that isn't frozen is left alone.
+pub struct EndFreeze {
+    branch: String,
+}
+
+impl EndFreeze {
+    pub fn new(branch: &str) -> EndFreeze {
+        EndFreeze {
+            branch: branch.to_string(),
+        }
+    }
+}
+
+impl Step for EndFreeze {
+    fn run_step(&self) -> RunStepResult {
+        fetch_freezes();
+        let repo = open_repo();
+
+        let name = format!("{}{}", FREEZE_PREFIX, self.branch);
+        let freeze = match remote_refs::find(&repo, &name) {
+            Some(freeze) => freeze,
+            None => {
+                println!("{}", format!("{} isn't frozen", self.branch).yellow());
+                return RunStepResult::Ok;
+            }
+        };
+
+        // Only if no one else changed the freeze since the fetch
+        if remote_refs::compare_and_swap(&repo, &name, Some(freeze.oid), None) {
+            RunStepResult::Ok
+        } else {
+            eprintln!(
+                "{}",
+                format!("Failed to end the freeze of {}", self.branch).red()
+            );
+            RunStepResult::Err
+        }
+    }
+
+    fn as_string(&self) -> String {
+        format!("end the freeze of {} on origin", self.branch)
+    }
+
+    fn risk(&self) -> Risk {
+        Risk::RemoteWrite
+    }
+}
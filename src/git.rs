@@ -21,6 +21,10 @@ impl Git {
         Git::from(format!("branch {}", branch))
     }
 
+    pub fn fetch() -> Self {
+        Git::from("fetch origin")
+    }
+
     pub fn pull() -> Self {
         Git::from("pull")
     }
@@ -41,6 +45,11 @@ impl Git {
         Git::from("push development develop:master")
     }
 
+    /// Push all the refspecs to origin in one go. Either all of them are updated or none are.
+    pub fn atomic_push(refspecs: &[String]) -> Self {
+        Git::from(format!("push --atomic origin {}", refspecs.join(" ")))
+    }
+
     pub fn push_and_set_upstream(branch: &str) -> Self {
         Git::from(format!("push --set-upstream origin {}", branch))
     }
@@ -105,6 +114,16 @@ impl From<String> for Git {
     }
 }
 
+/// Git with these arguments, which aren't split any further.
+impl From<Vec<String>> for Git {
+    fn from(args: Vec<String>) -> Git {
+        Git {
+            command: String::from("git"),
+            args,
+        }
+    }
+}
+
 /// Returns if a branch with the given name exists.
 pub fn branch_exists(needle: &str) -> bool {
     let repo = open_repo();
@@ -140,60 +159,199 @@ pub fn current_branch() -> String {
         .to_string()
 }
 
-fn open_repo() -> Repository {
+/// Get the name of the current branch, or `None` if HEAD is detached.
+pub fn head_branch() -> Option<String> {
+    let repo = open_repo();
+    let head = repo.head().ok()?;
+
+    if head.is_branch() {
+        head.shorthand().map(ToString::to_string)
+    } else {
+        None
+    }
+}
+
+/// Get the SHA of the commit HEAD points at.
+pub fn head_commit() -> Option<String> {
+    open_repo()
+        .head()
+        .ok()
+        .and_then(|head| head.target())
+        .map(|oid| oid.to_string())
+}
+
+/// Format a time from git in the local timezone.
+pub fn format_time(time: git2::Time) -> String {
+    chrono::DateTime::from_timestamp(time.seconds(), 0)
+        .map(|time| {
+            time.with_timezone(&chrono::Local)
+                .format("%Y-%m-%d %H:%M")
+                .to_string()
+        })
+        .unwrap_or_default()
+}
+
+/// The repo in the current directory. Panics if there is none, so commands check with
+/// `find_repo` before they run.
+pub fn open_repo() -> Repository {
     Repository::open(".").expect("failed to open repo in current directory")
 }
 
-pub fn current_branch_with_confirm(
-    question: impl Fn(&str) -> String,
-    default: ConfirmDefault,
-) -> String {
-    let current_branch = current_branch();
+/// The repo in the current directory, or why it can't be opened.
+pub fn find_repo() -> Result<Repository, String> {
+    Repository::open(".").map_err(|err| format!("Not in a git repo: {}", err.message()))
+}
 
-    if confirm(&question(&current_branch), default) {
-        current_branch
-    } else {
-        std::process::exit(0)
-    }
+/// The commit of a local branch, or of the branch on origin if there is no local branch yet.
+pub fn resolve_branch(repo: &Repository, branch: &str) -> Result<git2::Oid, git2::Error> {
+    repo.revparse_single(&format!("refs/heads/{}", branch))
+        .or_else(|_| repo.revparse_single(&format!("refs/remotes/origin/{}", branch)))
+        .and_then(|object| object.peel_to_commit())
+        .map(|commit| commit.id())
 }
 
-fn confirm(question: &str, default: ConfirmDefault) -> bool {
-    use std::io::{self, Read, Write};
+/// Set to answer yes to every prompt, like `--yes`.
+pub const YES_ENV: &str = "API_GIT_YES";
+/// Set to take the default answer of every prompt, like `--no-input`.
+pub const NO_INPUT_ENV: &str = "API_GIT_NO_INPUT";
+
+/// How prompts are answered.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Prompts {
+    /// Ask on the terminal.
+    Ask,
+    /// Answer yes to everything.
+    Yes,
+    /// Take the default answer without asking.
+    Defaults,
+}
 
-    match default {
-        ConfirmDefault::Yes => {
-            print!("{}? Y/n ", question);
+impl Prompts {
+    /// From `API_GIT_YES` and `API_GIT_NO_INPUT`.
+    pub fn from_env() -> Prompts {
+        let is_set =
+            |name: &str| std::env::var(name).is_ok_and(|value| !value.is_empty() && value != "0");
+
+        if is_set(YES_ENV) {
+            Prompts::Yes
+        } else if is_set(NO_INPUT_ENV) {
+            Prompts::Defaults
+        } else {
+            Prompts::Ask
         }
-        ConfirmDefault::No => {
-            print!("{}? y/N ", question);
+    }
+
+    /// Answer prompts this way for the rest of the run. It's stored in the environment so hooks
+    /// and plugins answer the same way.
+    pub fn set(self) {
+        match self {
+            Prompts::Ask => {}
+            Prompts::Yes => std::env::set_var(YES_ENV, "1"),
+            Prompts::Defaults => std::env::set_var(NO_INPUT_ENV, "1"),
         }
     }
-    io::stdout().flush().expect("failed to flush stdout");
+}
+
+/// Ask a yes or no question. Fails if there is no terminal to ask on.
+pub fn confirm(question: &str, default: ConfirmDefault) -> Result<bool, String> {
+    use std::io::{self, BufRead, IsTerminal, Write};
 
-    let input = io::stdin()
-        .bytes()
-        .next()
-        .and_then(|result| result.ok())
-        .map(|byte| byte as char)
-        .expect("failed to read from stdin");
+    let default_answer = match default {
+        ConfirmDefault::Yes => true,
+        ConfirmDefault::No => false,
+    };
+    let hint = if default_answer { "Y/n" } else { "y/N" };
 
-    if input == '\n' {
-        match default {
-            ConfirmDefault::Yes => return true,
-            ConfirmDefault::No => return false,
+    match Prompts::from_env() {
+        Prompts::Yes => {
+            println!("{}? {} yes", question, hint);
+            return Ok(true);
         }
-    } else {
-        if input == 'y' {
-            return true;
+        Prompts::Defaults => {
+            println!(
+                "{}? {} {}",
+                question,
+                hint,
+                if default_answer { "yes" } else { "no" }
+            );
+            return Ok(default_answer);
+        }
+        Prompts::Ask => {}
+    }
+
+    if !io::stdin().is_terminal() {
+        return Err(no_terminal(question));
+    }
+
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+
+    loop {
+        print!("{}? {} ", question, hint);
+        io::stdout().flush().expect("failed to flush stdout");
+
+        let line = match lines.next() {
+            Some(Ok(line)) => line,
+            _ => return Err("No answer given".to_string()),
+        };
+
+        match line.trim().to_lowercase().as_str() {
+            "" => return Ok(default_answer),
+            "y" | "yes" => return Ok(true),
+            "n" | "no" => return Ok(false),
+            _ => println!("Please answer yes or no"),
         }
+    }
+}
+
+fn no_terminal(question: &str) -> String {
+    format!(
+        "Can't ask \"{}?\" because stdin isn't a terminal. Answer with --yes or --no-input, or by setting {} or {}",
+        question, YES_ENV, NO_INPUT_ENV
+    )
+}
 
-        if input == 'n' {
-            return false;
+/// Ask for one of `count` numbered options, starting at 1. Defaults to the first one. Returns
+/// the index of the chosen option, or `None` if the user answered no. Fails if there is no
+/// terminal to ask on.
+pub fn choose(question: &str, count: usize) -> Result<Option<usize>, String> {
+    use std::io::{self, BufRead, IsTerminal, Write};
+
+    let hint = format!("[1-{}/n]", count);
+
+    match Prompts::from_env() {
+        Prompts::Yes | Prompts::Defaults => {
+            println!("{}? {} 1", question, hint);
+            return Ok(Some(0));
         }
+        Prompts::Ask => {}
     }
 
-    eprintln!("Invalid answer {:?}", input);
-    std::process::exit(1)
+    if !io::stdin().is_terminal() {
+        return Err(no_terminal(question));
+    }
+
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+
+    loop {
+        print!("{}? {} ", question, hint);
+        io::stdout().flush().expect("failed to flush stdout");
+
+        let line = match lines.next() {
+            Some(Ok(line)) => line,
+            _ => return Err("No answer given".to_string()),
+        };
+
+        match line.trim().to_lowercase().as_str() {
+            "" => return Ok(Some(0)),
+            "n" | "no" => return Ok(None),
+            answer => match answer.parse::<usize>() {
+                Ok(choice) if choice >= 1 && choice <= count => return Ok(Some(choice - 1)),
+                _ => println!("Please answer a number from 1 to {} or no", count),
+            },
+        }
+    }
 }
 
 #[derive(Debug, Copy, Clone)]
@@ -207,6 +365,22 @@ mod test {
     #[allow(unused_imports)]
     use super::*;
 
+    #[test]
+    fn test_risk() {
+        use crate::command_chain::{Risk, Step};
+
+        assert_eq!(Git::checkout("master").risk(), Risk::Local);
+        assert_eq!(Git::push().risk(), Risk::RemoteWrite);
+        assert_eq!(Git::force_push().risk(), Risk::Destructive);
+        assert_eq!(
+            Git::delete_remote_branch("feature").risk(),
+            Risk::Destructive
+        );
+        assert_eq!(Git::delete_branch("feature").risk(), Risk::Destructive);
+        assert_eq!(Git::push_staging().risk(), Risk::Deploy);
+        assert!(Git::push_develop().is_deploy());
+    }
+
     #[test]
     fn test_branch_exists() {
         assert!(branch_exists("master"));
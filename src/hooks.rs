@@ -0,0 +1,191 @@
+use crate::command_chain::{CommandChain, RunStepResult, Step};
+use crate::config::Config;
+use crate::git::open_repo;
+use std::fmt;
+use std::process;
+
+/// Shell commands from the config that run at points in a chain.
+///
+/// Hooks are set per command, in an `api-git-hook` section named after the command, or after the
+/// command and the branch being merged into, pushed or deployed:
+///
+/// ```text
+/// [api-git-hook "merge"]
+///     beforeChain = ./scripts/lint
+/// [api-git-hook "merge:master"]
+///     beforeMerge = cargo test
+/// [api-git-hook "*"]
+///     afterDeploy = ./scripts/notify
+/// ```
+///
+/// `*` matches every command. A point can be given multiple times to run several hooks.
+pub struct Hooks {
+    command: String,
+    config: Option<Config>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum HookPoint {
+    /// Before the first step.
+    BeforeChain,
+    /// Before merging a branch into the target. When merging with a rebase, the rebased branch is
+    /// checked out.
+    BeforeMerge,
+    /// After merging a branch into the target, which is checked out.
+    AfterMerge,
+    /// Before pushing the target to origin.
+    BeforePush,
+    /// After deploying, the target being the environment.
+    AfterDeploy,
+}
+
+/// Run a hook. It fails the chain if the shell command exits with an error.
+///
+/// The command gets the environment variables `API_GIT_COMMAND`, `API_GIT_HOOK`,
+/// `API_GIT_BRANCH`, `API_GIT_TARGET` and the SHAs `API_GIT_HEAD_SHA`, `API_GIT_BRANCH_SHA` and
+/// `API_GIT_TARGET_SHA`, as they are when the hook runs.
+pub struct Hook {
+    command: String,
+    point: HookPoint,
+    shell: String,
+    branch: Option<String>,
+    target: Option<String>,
+}
+
+impl Hooks {
+    /// The hooks configured for `command` in the repo in the current directory.
+    pub fn for_command(command: &str) -> Result<Hooks, String> {
+        Ok(Hooks {
+            command: command.to_string(),
+            config: Some(Config::load()?),
+        })
+    }
+
+    /// No hooks, for building chains without a repo.
+    pub fn none(command: &str) -> Hooks {
+        Hooks {
+            command: command.to_string(),
+            config: None,
+        }
+    }
+
+    /// Add the hooks for `point`. `branch` is the branch being merged and `target` the branch it
+    /// is merged into, pushed or deployed.
+    pub fn add(
+        &self,
+        c: &mut CommandChain,
+        point: HookPoint,
+        branch: Option<&str>,
+        target: Option<&str>,
+    ) {
+        for shell in self.commands(point, target) {
+            c.add(Hook {
+                command: self.command.clone(),
+                point,
+                shell,
+                branch: branch.map(ToString::to_string),
+                target: target.map(ToString::to_string),
+            });
+        }
+    }
+
+    fn commands(&self, point: HookPoint, target: Option<&str>) -> Vec<String> {
+        let config = match &self.config {
+            Some(config) => config,
+            None => return vec![],
+        };
+
+        sections(&self.command, target)
+            .iter()
+            .flat_map(|section| config.get_all(&format!("api-git-hook.{}.{}", section, point)))
+            .collect()
+    }
+}
+
+/// The config sections that apply to a command, from the most general to the most specific.
+fn sections(command: &str, target: Option<&str>) -> Vec<String> {
+    let mut sections = vec!["*".to_string(), command.to_string()];
+    if let Some(target) = target {
+        sections.push(format!("*:{}", target));
+        sections.push(format!("{}:{}", command, target));
+    }
+    sections
+}
+
+impl fmt::Debug for Hooks {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Hooks")
+            .field("command", &self.command)
+            .finish()
+    }
+}
+
+impl fmt::Display for HookPoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            HookPoint::BeforeChain => "beforeChain",
+            HookPoint::BeforeMerge => "beforeMerge",
+            HookPoint::AfterMerge => "afterMerge",
+            HookPoint::BeforePush => "beforePush",
+            HookPoint::AfterDeploy => "afterDeploy",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl Step for Hook {
+    fn run_step(&self) -> RunStepResult {
+        let repo = open_repo();
+        let sha = |branch: &Option<String>| {
+            branch
+                .as_ref()
+                .and_then(|branch| repo.refname_to_id(&format!("refs/heads/{}", branch)).ok())
+                .map(|oid| oid.to_string())
+                .unwrap_or_default()
+        };
+        let head = repo
+            .head()
+            .ok()
+            .and_then(|head| head.target())
+            .map(|oid| oid.to_string())
+            .unwrap_or_default();
+
+        let status = process::Command::new("sh")
+            .arg("-c")
+            .arg(&self.shell)
+            .env("API_GIT_COMMAND", &self.command)
+            .env("API_GIT_HOOK", self.point.to_string())
+            .env("API_GIT_BRANCH", self.branch.as_deref().unwrap_or(""))
+            .env("API_GIT_TARGET", self.target.as_deref().unwrap_or(""))
+            .env("API_GIT_HEAD_SHA", head)
+            .env("API_GIT_BRANCH_SHA", sha(&self.branch))
+            .env("API_GIT_TARGET_SHA", sha(&self.target))
+            .status()
+            .expect("failed to execute hook");
+
+        if status.success() {
+            RunStepResult::Ok
+        } else {
+            RunStepResult::Err
+        }
+    }
+
+    fn as_string(&self) -> String {
+        format!("{} # {} hook", self.shell, self.point)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[test]
+    fn test_sections() {
+        assert_eq!(sections("start", None), vec!["*", "start"]);
+        assert_eq!(
+            sections("merge", Some("master")),
+            vec!["*", "merge", "*:master", "merge:master"]
+        );
+    }
+}
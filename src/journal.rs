@@ -0,0 +1,73 @@
+use crate::git::open_repo;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+
+/// State about the current run that has to survive the process exiting, so a failed command can
+/// be resumed with `--from-step`.
+///
+/// Stored as JSON in `.git/api-git/journal.json`.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Journal {
+    /// The command used to resume the run.
+    pub command: String,
+    /// The branch that was checked out when the run started.
+    pub original_branch: Option<String>,
+    /// The commit HEAD pointed at when the run started.
+    pub original_head: Option<String>,
+    /// The stash commit holding local changes put away by `--autostash`.
+    pub autostash: Option<String>,
+    /// The units that were rolled back or skipped because of that, whose branches aren't pushed.
+    #[serde(default)]
+    pub failed_units: Vec<String>,
+}
+
+impl Journal {
+    pub fn new(
+        command: &str,
+        original_branch: Option<String>,
+        original_head: Option<String>,
+    ) -> Journal {
+        Journal {
+            command: command.to_string(),
+            original_branch,
+            original_head,
+            autostash: None,
+            failed_units: vec![],
+        }
+    }
+
+    /// Load the journal of the last run, if there is one.
+    pub fn load() -> Option<Journal> {
+        let contents = fs::read_to_string(journal_path()).ok()?;
+        serde_json::from_str(&contents).ok()
+    }
+
+    pub fn save(&self) {
+        let path = journal_path();
+        fs::create_dir_all(path.parent().expect("journal has no parent dir"))
+            .expect("failed to create .git/api-git");
+
+        let json = serde_json::to_string_pretty(self).expect("failed to serialize journal");
+        fs::write(&path, json).expect("failed to write journal");
+    }
+
+    /// Load the journal, change it, and save it again.
+    pub fn update<F>(f: F)
+    where
+        F: FnOnce(&mut Journal),
+    {
+        let mut journal = Journal::load().unwrap_or_default();
+        f(&mut journal);
+        journal.save();
+    }
+}
+
+/// The directory api-git keeps its own state in, inside `.git`.
+pub fn state_dir() -> PathBuf {
+    open_repo().path().join("api-git")
+}
+
+fn journal_path() -> PathBuf {
+    state_dir().join("journal.json")
+}
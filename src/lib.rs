@@ -0,0 +1,49 @@
+//! Handles common API git operations.
+//!
+//! Commands are built as a [`CommandChain`](command_chain::CommandChain) of
+//! [`Step`](command_chain::Step)s and run with the journal, hooks, report and audit log by
+//! [`CommandArgs::run_command`](commands::CommandArgs::run_command). The `api-git` binary is a
+//! thin CLI on top of this, and other tools can drive the same commands:
+//!
+//! ```no_run
+//! use api_git::commands::merge::{merge_command, MergeArgs};
+//! use api_git::commands::{CommandArgs, RunOptions};
+//!
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let report = MergeArgs::for_repo("master", &["feature"])?
+//!     .run_command(&RunOptions::default(), merge_command)?;
+//! assert!(report.succeeded());
+//! # Ok(())
+//! # }
+//! ```
+
+extern crate clap;
+extern crate colored;
+
+pub mod audit;
+pub mod command;
+pub mod command_chain;
+pub mod commands;
+pub mod config;
+pub mod git;
+pub mod hooks;
+pub mod journal;
+pub mod preview;
+pub mod propagation;
+pub mod report;
+pub mod workflow;
+
+mod atomic_push;
+mod autostash;
+mod branch_delete;
+mod branch_policy;
+mod branch_update;
+mod deploys;
+mod freeze;
+mod lock;
+mod merge_policy;
+mod native;
+mod remote_refs;
+mod return_to_start;
+mod snapshot;
+mod string_ext;
@@ -0,0 +1,240 @@
+use crate::command_chain::{Risk, RunStepResult, Step};
+use crate::git::{format_time, open_repo};
+use crate::remote_refs::{self, RemoteRef};
+use colored::*;
+use git2::Repository;
+
+/// Where deploy locks are stored, locally and on origin. Each locked environment has a ref whose
+/// message has the branch being deployed.
+pub const LOCK_PREFIX: &str = "refs/api-git/locks/";
+
+/// An environment someone is deploying to.
+pub struct Lock {
+    pub env: String,
+    pub branch: String,
+    remote_ref: RemoteRef,
+}
+
+impl Lock {
+    fn from_ref(remote_ref: RemoteRef) -> Lock {
+        Lock {
+            env: remote_ref.name[LOCK_PREFIX.len()..].to_string(),
+            branch: remote_ref
+                .body()
+                .strip_prefix("Branch: ")
+                .unwrap_or("")
+                .to_string(),
+            remote_ref,
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        format!(
+            "{} is locked by {} since {} for {}",
+            self.env,
+            self.remote_ref.author,
+            format_time(self.remote_ref.time),
+            self.branch
+        )
+    }
+}
+
+/// Update the local lock refs from origin.
+pub fn fetch_locks() {
+    remote_refs::fetch(LOCK_PREFIX, "deploy locks");
+}
+
+/// The lock of an environment, from the local lock refs.
+pub fn find_lock(repo: &Repository, env: &str) -> Option<Lock> {
+    remote_refs::find(repo, &lock_ref(env)).map(Lock::from_ref)
+}
+
+/// Every lock, from the local lock refs.
+pub fn all_locks(repo: &Repository) -> Vec<Lock> {
+    remote_refs::find_all(repo, LOCK_PREFIX)
+        .into_iter()
+        .map(Lock::from_ref)
+        .collect()
+}
+
+fn lock_ref(env: &str) -> String {
+    format!("{}{}", LOCK_PREFIX, env)
+}
+
+/// Take the lock of `env` for `branch`, replacing `expected` which is `None` if nobody has it.
+/// Fails if the lock changed on origin in the meantime.
+fn take(repo: &Repository, env: &str, branch: &str, expected: Option<&Lock>) -> RunStepResult {
+    let message = format!("Lock {}\n\nBranch: {}\n", env, branch);
+    let swapped = remote_refs::create_commit(repo, &message).map(|oid| {
+        remote_refs::compare_and_swap(
+            repo,
+            &lock_ref(env),
+            expected.map(|lock| lock.remote_ref.oid),
+            Some(oid),
+        )
+    });
+
+    match swapped {
+        Ok(true) => RunStepResult::Ok,
+        Ok(false) => {
+            eprintln!(
+                "{}",
+                format!("The lock on {} changed on origin, try again", env).red()
+            );
+            RunStepResult::Err
+        }
+        Err(err) => {
+            eprintln!("{}", err.message().red());
+            RunStepResult::Err
+        }
+    }
+}
+
+/// Lock an environment on origin so nobody else deploys to it. If the current user already has
+/// the lock, it's taken again for this branch.
+pub struct AcquireLock {
+    env: String,
+    branch: String,
+}
+
+impl AcquireLock {
+    pub fn new(env: &str, branch: &str) -> AcquireLock {
+        AcquireLock {
+            env: env.to_string(),
+            branch: branch.to_string(),
+        }
+    }
+}
+
+impl Step for AcquireLock {
+    fn run_step(&self) -> RunStepResult {
+        fetch_locks();
+        let repo = open_repo();
+
+        match find_lock(&repo, &self.env) {
+            Some(lock) if lock.remote_ref.is_mine(&repo) => {
+                println!("You already have the lock on {}", self.env);
+                take(&repo, &self.env, &self.branch, Some(&lock))
+            }
+            Some(lock) => {
+                eprintln!("{}", lock.describe().red());
+                eprintln!(
+                    "Wait for it to be released, or take it over with `api-git lock steal {}`",
+                    self.env
+                );
+                RunStepResult::Err
+            }
+            None => take(&repo, &self.env, &self.branch, None),
+        }
+    }
+
+    fn as_string(&self) -> String {
+        format!("lock {} on origin for {}", self.env, self.branch)
+    }
+
+    fn risk(&self) -> Risk {
+        Risk::RemoteWrite
+    }
+}
+
+/// Release the current user's lock of an environment on origin.
+pub struct ReleaseLock {
+    env: String,
+    /// Fail if the lock belongs to someone else. Otherwise it's left alone with a warning.
+    strict: bool,
+}
+
+impl ReleaseLock {
+    pub fn new(env: &str) -> ReleaseLock {
+        ReleaseLock {
+            env: env.to_string(),
+            strict: true,
+        }
+    }
+
+    /// Release the lock at the end of a deploy. The deploy is done, so nothing fails.
+    pub fn after_deploy(env: &str) -> ReleaseLock {
+        ReleaseLock {
+            env: env.to_string(),
+            strict: false,
+        }
+    }
+
+    fn fail(&self, message: String) -> RunStepResult {
+        if self.strict {
+            eprintln!("{}", message.red());
+            RunStepResult::Err
+        } else {
+            println!("{}", message.yellow());
+            RunStepResult::Ok
+        }
+    }
+}
+
+impl Step for ReleaseLock {
+    fn run_step(&self) -> RunStepResult {
+        fetch_locks();
+        let repo = open_repo();
+
+        match find_lock(&repo, &self.env) {
+            None => self.fail(format!("{} isn't locked", self.env)),
+            Some(lock) if !lock.remote_ref.is_mine(&repo) => self.fail(format!(
+                "{}. Take it over with `api-git lock steal {}` first",
+                lock.describe(),
+                self.env
+            )),
+            Some(lock) => {
+                let name = lock_ref(&self.env);
+                if remote_refs::compare_and_swap(&repo, &name, Some(lock.remote_ref.oid), None) {
+                    RunStepResult::Ok
+                } else {
+                    self.fail(format!("Failed to release the lock on {}", self.env))
+                }
+            }
+        }
+    }
+
+    fn as_string(&self) -> String {
+        format!("release the lock on {}", self.env)
+    }
+
+    fn risk(&self) -> Risk {
+        Risk::RemoteWrite
+    }
+}
+
+/// Take over the lock of an environment from someone else.
+pub struct StealLock {
+    env: String,
+    branch: String,
+}
+
+impl StealLock {
+    pub fn new(env: &str, branch: &str) -> StealLock {
+        StealLock {
+            env: env.to_string(),
+            branch: branch.to_string(),
+        }
+    }
+}
+
+impl Step for StealLock {
+    fn run_step(&self) -> RunStepResult {
+        fetch_locks();
+        let repo = open_repo();
+
+        let lock = find_lock(&repo, &self.env);
+        if let Some(lock) = &lock {
+            println!("Taking over from: {}", lock.describe());
+        }
+        take(&repo, &self.env, &self.branch, lock.as_ref())
+    }
+
+    fn as_string(&self) -> String {
+        format!("take over the lock on {} for {}", self.env, self.branch)
+    }
+
+    fn risk(&self) -> Risk {
+        Risk::RemoteWrite
+    }
+}
@@ -1,22 +1,26 @@
 extern crate clap;
 extern crate colored;
 
-mod command;
-mod string_ext;
-
-mod commands;
-
-mod command_chain;
-mod git;
-
-use crate::commands::{
-    merge,
+use api_git::commands::{
+    self, deploys, freeze, history, lock, merge,
     on_remote::{self, Remote},
-    ship_hotfix, start,
+    restore_branch, ship_hotfix, start, RunError,
 };
-use clap::{App, Arg, SubCommand};
+use api_git::config::Config;
+use api_git::git::Prompts;
+use api_git::workflow::Workflow;
+use clap::{App, AppSettings, Arg, SubCommand};
 
 fn main() {
+    // An invalid config is only reported by the commands that read it, so the others still work
+    let config = git2::Repository::open(".")
+        .ok()
+        .map(|repo| Config::for_repo(&repo));
+    let workflows = match &config {
+        Some(Ok(config)) => Workflow::load_all(config),
+        _ => vec![],
+    };
+
     let dry_run = Arg::with_name("dry-run")
         .long("dry-run")
         .help("Don't run stuff, just print what would happen");
@@ -27,16 +31,69 @@ fn main() {
         .value_name("STEP")
         .help("Start the command from the given step. Used when a previous command failed and needs to be resumed");
 
+    let autostash = Arg::with_name("autostash")
+        .long("autostash")
+        .help("Stash local changes before running and restore them on the current branch afterwards. Can also be enabled with the api-git.autostash config");
+
+    let restore_branch = Arg::with_name("restore-branch")
+        .long("restore-branch")
+        .help("Go back to the branch you started on if a step fails, unless there is a conflict to resolve. Can also be enabled with the api-git.restoreBranch config");
+
+    let report = Arg::with_name("report")
+        .long("report")
+        .takes_value(true)
+        .value_name("FILE")
+        .help("Also write the report printed at the end as Markdown to this file");
+
+    let confirm = Arg::with_name("confirm")
+        .long("confirm")
+        .help("Show the commits, diffstat and steps and ask before running. Can also be enabled with the api-git.confirm config");
+
+    let allow_destructive = Arg::with_name("allow-destructive")
+        .long("allow-destructive")
+        .help("Run destructive and deploy steps without asking when the api-git.confirmRisky config is set");
+
+    let override_policy = Arg::with_name("override-policy")
+        .long("override-policy")
+        .takes_value(true)
+        .value_name("REASON")
+        .help("Merge even though the api-git.policy rules are broken. The reason is recorded in the audit log");
+
+    let override_freeze = Arg::with_name("override-freeze")
+        .long("override-freeze")
+        .takes_value(true)
+        .value_name("REASON")
+        .help("Run even though a branch it updates is frozen with `api-git freeze start`");
+
+    let yes = Arg::with_name("yes")
+        .long("yes")
+        .global(true)
+        .help("Answer yes to every prompt. Can also be set with API_GIT_YES=1");
+
+    let no_input = Arg::with_name("no-input")
+        .long("no-input")
+        .global(true)
+        .conflicts_with("yes")
+        .help("Don't prompt, take the default answer instead. Can also be set with API_GIT_NO_INPUT=1");
+
     let mut app = App::new("api-git")
         .version(env!("CARGO_PKG_VERSION"))
         .author("David Pedersen <david.pdrsn@gmail.com>")
         .about("Handles common API git operations")
+        .setting(AppSettings::AllowExternalSubcommands)
+        .arg(&yes)
+        .arg(&no_input)
         .subcommand(
             SubCommand::with_name("start")
                 .about("Start a new branch")
                 .arg(&dry_run)
                 .arg(&from_step)
-                .arg(Arg::with_name("BRANCH").help("The name of the branch that will be created"))
+                .arg(&autostash)
+                .arg(&restore_branch)
+                .arg(&report)
+                .arg(&confirm)
+                .arg(&allow_destructive)
+                .arg(Arg::with_name("BRANCH").help("The name of the branch that will be created. Free text is turned into a valid branch name"))
                 .arg(
                     Arg::with_name("push")
                         .long("push")
@@ -54,12 +111,26 @@ fn main() {
                         .long("prefix")
                         .short("x")
                         .help("Prefix the new branch with the name of the base branch. Useful for sprint feature branches that goes into release branches"),
+                ).arg(
+                    Arg::with_name("ticket")
+                        .long("ticket")
+                        .short("t")
+                        .takes_value(true)
+                        .value_name("TICKET")
+                        .help("The ticket ID used for {ticket} in the branch name template"),
                 ),
         ).subcommand(
             SubCommand::with_name("merge")
                 .about("Merge one or more branches")
                 .arg(&dry_run)
                 .arg(&from_step)
+                .arg(&autostash)
+                .arg(&restore_branch)
+                .arg(&report)
+                .arg(&confirm)
+                .arg(&allow_destructive)
+                .arg(&override_freeze)
+                .arg(&override_policy)
                 .arg(Arg::with_name("BRANCH").multiple(true).help("The branch(es) that will be merged"))
                 .arg(
                     Arg::with_name("into")
@@ -78,6 +149,12 @@ fn main() {
                 .about("Merge master into staging and develop and deploy")
                 .arg(&dry_run)
                 .arg(&from_step)
+                .arg(&autostash)
+                .arg(&restore_branch)
+                .arg(&report)
+                .arg(&confirm)
+                .arg(&allow_destructive)
+                .arg(&override_freeze)
         ).subcommand(
             SubCommand::with_name("on-staging")
                 .about("Merge branch into staging and deploy to staging")
@@ -89,6 +166,13 @@ fn main() {
                 )
                 .arg(&dry_run)
                 .arg(&from_step)
+                .arg(&autostash)
+                .arg(&restore_branch)
+                .arg(&report)
+                .arg(&confirm)
+                .arg(&allow_destructive)
+                .arg(&override_freeze)
+                .arg(&override_policy)
         ).subcommand(
             SubCommand::with_name("on-develop")
                 .about("Merge branch into develop and deploy to develop")
@@ -100,23 +184,181 @@ fn main() {
                 )
                 .arg(&dry_run)
                 .arg(&from_step)
-            );
+                .arg(&autostash)
+                .arg(&restore_branch)
+                .arg(&report)
+                .arg(&confirm)
+                .arg(&allow_destructive)
+                .arg(&override_freeze)
+                .arg(&override_policy)
+        ).subcommand(
+            SubCommand::with_name("restore-branch")
+                .about("Recreate a deleted branch, from the archive, the reflog or origin's branches")
+                .arg(Arg::with_name("BRANCH").required(true).help("The name of the deleted branch"))
+                .arg(
+                    Arg::with_name("commit")
+                        .long("commit")
+                        .takes_value(true)
+                        .value_name("SHA")
+                        .help("Restore the branch at this commit instead of looking for it"),
+                ).arg(
+                    Arg::with_name("push")
+                        .long("push")
+                        .short("p")
+                        .help("Also push the branch and setup tracking with remote branch"),
+                )
+                .arg(&dry_run)
+                .arg(&from_step)
+                .arg(&autostash)
+                .arg(&restore_branch)
+                .arg(&report)
+                .arg(&confirm)
+                .arg(&allow_destructive)
+        ).subcommand(
+            SubCommand::with_name("freeze")
+                .about("Stop merges and deploys into branches, for everyone working with origin")
+                .subcommand(
+                    SubCommand::with_name("start")
+                        .about("Freeze branches")
+                        .arg(Arg::with_name("BRANCH").multiple(true).help("The branches to freeze. Defaults to master"))
+                        .arg(
+                            Arg::with_name("reason")
+                                .long("reason")
+                                .takes_value(true)
+                                .required(true)
+                                .value_name("REASON")
+                                .help("Why the branches are frozen"),
+                        )
+                        .arg(&dry_run)
+                        .arg(&from_step)
+                        .arg(&report),
+                ).subcommand(
+                    SubCommand::with_name("end")
+                        .about("End the freeze of branches")
+                        .arg(Arg::with_name("BRANCH").multiple(true).help("The branches to unfreeze. Defaults to master"))
+                        .arg(&dry_run)
+                        .arg(&from_step)
+                        .arg(&report),
+                ).subcommand(
+                    SubCommand::with_name("status")
+                        .about("List the frozen branches"),
+                ),
+        ).subcommand(
+            SubCommand::with_name("lock")
+                .about("Show and manage the deploy locks on origin")
+                .subcommand(
+                    SubCommand::with_name("status")
+                        .about("List the locked environments"),
+                ).subcommand(
+                    SubCommand::with_name("release")
+                        .about("Release your lock on an environment")
+                        .arg(Arg::with_name("ENV").required(true).help("The environment, like staging"))
+                        .arg(&dry_run)
+                        .arg(&report),
+                ).subcommand(
+                    SubCommand::with_name("steal")
+                        .about("Take over someone else's lock on an environment")
+                        .arg(Arg::with_name("ENV").required(true).help("The environment, like staging"))
+                        .arg(&dry_run)
+                        .arg(&report),
+                ),
+        ).subcommand(
+            SubCommand::with_name("deploys")
+                .about("List the deploys to an environment and what changed in each")
+                .arg(Arg::with_name("ENV").required(true).help("The environment, like staging")),
+        ).subcommand(
+            SubCommand::with_name("history")
+                .about("List past runs of api-git in this repo")
+                .arg(
+                    Arg::with_name("shared")
+                        .long("shared")
+                        .help("Read the shared audit log set with api-git.auditLog"),
+                ).subcommand(
+                    SubCommand::with_name("show")
+                        .about("Show the steps and ref changes of a run")
+                        .arg(Arg::with_name("ID").required(true).help("The id of the run")),
+                ),
+        );
+
+    for workflow in &workflows {
+        app = app.subcommand(
+            commands::workflow::subcommand(workflow)
+                .arg(&dry_run)
+                .arg(&from_step)
+                .arg(&autostash)
+                .arg(&restore_branch)
+                .arg(&report)
+                .arg(&confirm)
+                .arg(&allow_destructive),
+        );
+    }
 
     let matches = app.clone().get_matches();
 
-    if let Some(matches) = matches.subcommand_matches("start") {
-        start::run_start(matches);
+    let flag = |name: &str| {
+        matches.is_present(name)
+            || matches
+                .subcommand()
+                .1
+                .is_some_and(|matches| matches.is_present(name))
+    };
+    if flag("yes") {
+        Prompts::Yes.set();
+    } else if flag("no-input") {
+        Prompts::Defaults.set();
+    }
+
+    let result = if let Some(matches) = matches.subcommand_matches("start") {
+        start::run_start(matches)
     } else if let Some(matches) = matches.subcommand_matches("merge") {
-        merge::run_merge(matches);
+        merge::run_merge(matches)
     } else if let Some(matches) = matches.subcommand_matches("ship-hotfix") {
-        ship_hotfix::run_ship_hotfix(matches);
+        ship_hotfix::run_ship_hotfix(matches)
     } else if let Some(matches) = matches.subcommand_matches("on-staging") {
-        on_remote::run_on_remote(Remote::Staging, matches);
+        on_remote::run_on_remote(Remote::Staging, matches)
     } else if let Some(matches) = matches.subcommand_matches("on-develop") {
-        on_remote::run_on_remote(Remote::Develop, matches);
+        on_remote::run_on_remote(Remote::Develop, matches)
+    } else if let Some(matches) = matches.subcommand_matches("restore-branch") {
+        restore_branch::run_restore_branch(matches)
+    } else if let Some(matches) = matches.subcommand_matches("freeze") {
+        freeze::run_freeze(matches)
+    } else if let Some(matches) = matches.subcommand_matches("lock") {
+        lock::run_lock(matches)
+    } else if let Some(matches) = matches.subcommand_matches("deploys") {
+        deploys::run_deploys(matches)
+    } else if let Some(matches) = matches.subcommand_matches("history") {
+        history::run_history(matches)
+    } else if let Some((workflow, matches)) = workflows.iter().find_map(|workflow| {
+        matches
+            .subcommand_matches(&workflow.name)
+            .map(|matches| (workflow, matches))
+    }) {
+        commands::workflow::run_workflow(workflow, matches)
+    } else if let ((_, Some(_)), Some(Err(err))) = (matches.subcommand(), config) {
+        // The command may be one of the workflows the config failed to declare
+        Err(RunError::Invalid(err))
+    } else if let (name, Some(matches)) = matches.subcommand() {
+        let core_options = App::new("api-git")
+            .arg(&yes)
+            .arg(&no_input)
+            .arg(&dry_run)
+            .arg(&from_step)
+            .arg(&autostash)
+            .arg(&restore_branch)
+            .arg(&report)
+            .arg(&confirm)
+            .arg(&allow_destructive);
+        commands::plugin::run_plugin(name, matches, core_options)
     } else {
         app.print_help().expect("failed to print help");
         println!();
         std::process::exit(1);
+    };
+
+    if let Err(err) = result {
+        if let RunError::Invalid(message) = &err {
+            eprintln!("{}", message);
+        }
+        std::process::exit(err.exit_code());
     }
 }
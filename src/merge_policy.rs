@@ -0,0 +1,291 @@
+use crate::branch_policy::parse_regex;
+use crate::config::Config;
+use crate::git::resolve_branch;
+use crate::preview::PlannedMerge;
+use chrono::{DateTime, Local, NaiveDate};
+use git2::{Oid, Repository};
+use regex::Regex;
+
+/// Rules a branch must follow before it's merged. Configured with the `api-git.policy.*` keys
+/// and checked by `merge` and `on-*` before they run.
+///
+/// - `maxBehindDays`: The branch must not be missing commits made to the target more than this
+///   many days ago, so it has to be rebased regularly.
+/// - `noFixups`: Whether `fixup!`, `squash!` and `amend!` commits are refused.
+/// - `messagePattern`: A regex the subject of every commit must match.
+/// - `maxCommits`: The most commits a branch can bring in.
+/// - `requireSignoff`: Whether every commit needs a `Signed-off-by` trailer.
+/// - `freeze`: A window like `2026-12-20..2027-01-03` in which nothing is merged into the
+///   `freezeBranch`es. Both days are included. Can be given multiple times.
+/// - `freezeBranch`: A branch the freeze windows apply to. Can be given multiple times, defaults
+///   to `master`.
+#[derive(Debug, Default)]
+pub struct MergePolicy {
+    max_behind_days: Option<i64>,
+    no_fixups: bool,
+    message_pattern: Option<Regex>,
+    max_commits: Option<usize>,
+    require_signoff: bool,
+    freezes: Vec<(NaiveDate, NaiveDate)>,
+    freeze_branches: Vec<String>,
+}
+
+/// A commit a branch brings in.
+#[derive(Debug)]
+struct BranchCommit {
+    sha: String,
+    message: String,
+}
+
+impl BranchCommit {
+    fn subject(&self) -> &str {
+        self.message.lines().next().unwrap_or("")
+    }
+}
+
+impl MergePolicy {
+    pub fn from_config(config: &Config) -> Result<MergePolicy, String> {
+        let mut freeze_branches = config.get_all("api-git.policy.freezeBranch");
+        if freeze_branches.is_empty() {
+            freeze_branches.push("master".to_string());
+        }
+
+        Ok(MergePolicy {
+            max_behind_days: config.get_i64("api-git.policy.maxBehindDays"),
+            no_fixups: config.get_bool("api-git.policy.noFixups").unwrap_or(false),
+            message_pattern: config
+                .get_str("api-git.policy.messagePattern")
+                .map(|pattern| parse_regex("api-git.policy.messagePattern", &pattern))
+                .transpose()?,
+            max_commits: config.get_count("api-git.policy.maxCommits")?,
+            require_signoff: config
+                .get_bool("api-git.policy.requireSignoff")
+                .unwrap_or(false),
+            freezes: config
+                .get_all("api-git.policy.freeze")
+                .iter()
+                .map(|window| {
+                    parse_window(window).ok_or_else(|| {
+                        format!(
+                            "Invalid freeze window in api-git.policy.freeze: {}. Use YYYY-MM-DD..YYYY-MM-DD",
+                            window
+                        )
+                    })
+                })
+                .collect::<Result<_, _>>()?,
+            freeze_branches,
+        })
+    }
+
+    /// The rules the merges break, checked against the refs as they are now.
+    pub fn violations(&self, merges: &[PlannedMerge]) -> Vec<String> {
+        let repo = match Repository::open(".") {
+            Ok(repo) => repo,
+            Err(_) => return vec![],
+        };
+        let now = Local::now();
+
+        let mut violations = vec![];
+        for merge in merges {
+            violations.append(&mut self.freeze_violations(merge, now.date_naive()));
+
+            let (branch, into) = match (
+                resolve_branch(&repo, &merge.branch),
+                resolve_branch(&repo, &merge.into),
+            ) {
+                (Ok(branch), Ok(into)) => (branch, into),
+                _ => continue,
+            };
+
+            let commits = walk(&repo, branch, into)
+                .iter()
+                .filter_map(|oid| repo.find_commit(*oid).ok())
+                .map(|commit| BranchCommit {
+                    sha: commit.id().to_string()[..7].to_string(),
+                    message: commit.message().unwrap_or("").to_string(),
+                })
+                .collect::<Vec<_>>();
+            violations.append(&mut self.commit_violations(merge, &commits));
+
+            if let Some(max_days) = self.max_behind_days {
+                // The oldest commit on the target that the branch is missing
+                let missing = walk(&repo, into, branch)
+                    .iter()
+                    .filter_map(|oid| repo.find_commit(*oid).ok())
+                    .map(|commit| commit.time().seconds())
+                    .min();
+
+                if let Some(days) = missing
+                    .and_then(|time| DateTime::from_timestamp(time, 0))
+                    .map(|time| (now.to_utc() - time).num_days())
+                    .filter(|days| *days > max_days)
+                {
+                    violations.push(format!(
+                        "{} is missing commits made to {} {} days ago. Rebase it, branches can't be more than {} days behind",
+                        merge.branch, merge.into, days, max_days
+                    ));
+                }
+            }
+        }
+        violations
+    }
+
+    fn commit_violations(&self, merge: &PlannedMerge, commits: &[BranchCommit]) -> Vec<String> {
+        let mut violations = vec![];
+
+        if let Some(max_commits) = self.max_commits {
+            if commits.len() > max_commits {
+                violations.push(format!(
+                    "{} has {} commits, more than the {} allowed",
+                    merge.branch,
+                    commits.len(),
+                    max_commits
+                ));
+            }
+        }
+
+        for commit in commits {
+            let subject = commit.subject();
+
+            if self.no_fixups
+                && ["fixup!", "squash!", "amend!"]
+                    .iter()
+                    .any(|prefix| subject.starts_with(prefix))
+            {
+                violations.push(format!(
+                    "{} \"{}\" has to be squashed before merging",
+                    commit.sha, subject
+                ));
+            }
+
+            if let Some(pattern) = &self.message_pattern {
+                if !pattern.is_match(subject) {
+                    violations.push(format!(
+                        "{} \"{}\" doesn't match the pattern {}",
+                        commit.sha,
+                        subject,
+                        pattern.as_str()
+                    ));
+                }
+            }
+
+            if self.require_signoff
+                && !commit
+                    .message
+                    .lines()
+                    .any(|line| line.starts_with("Signed-off-by: "))
+            {
+                violations.push(format!("{} \"{}\" isn't signed off", commit.sha, subject));
+            }
+        }
+
+        violations
+    }
+
+    fn freeze_violations(&self, merge: &PlannedMerge, today: NaiveDate) -> Vec<String> {
+        if !self.freeze_branches.contains(&merge.into) {
+            return vec![];
+        }
+
+        self.freezes
+            .iter()
+            .filter(|(start, end)| (*start..=*end).contains(&today))
+            .map(|(start, end)| {
+                format!(
+                    "{} is frozen from {} to {}, nothing can be merged into it",
+                    merge.into, start, end
+                )
+            })
+            .collect()
+    }
+}
+
+/// The commits reachable from `from` but not from `hide`.
+fn walk(repo: &Repository, from: Oid, hide: Oid) -> Vec<Oid> {
+    let mut walk = match repo.revwalk() {
+        Ok(walk) => walk,
+        Err(_) => return vec![],
+    };
+    if walk.push(from).and_then(|_| walk.hide(hide)).is_err() {
+        return vec![];
+    }
+    walk.filter_map(Result::ok).collect()
+}
+
+fn parse_window(window: &str) -> Option<(NaiveDate, NaiveDate)> {
+    let (start, end) = window.split_once("..")?;
+    let date = |date: &str| NaiveDate::parse_from_str(date.trim(), "%Y-%m-%d").ok();
+    Some((date(start)?, date(end)?))
+}
+
+#[cfg(test)]
+mod test {
+    #[allow(unused_imports)]
+    use super::*;
+
+    fn commit(sha: &str, message: &str) -> BranchCommit {
+        BranchCommit {
+            sha: sha.to_string(),
+            message: message.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_commit_violations() {
+        let policy = MergePolicy {
+            no_fixups: true,
+            message_pattern: Some(Regex::new("^[A-Z]+-[0-9]+ ").unwrap()),
+            max_commits: Some(2),
+            require_signoff: true,
+            ..MergePolicy::default()
+        };
+        let merge = PlannedMerge::new("feature", "master", true);
+
+        assert_eq!(
+            policy.commit_violations(
+                &merge,
+                &[
+                    commit("aaaaaaa", "API-1 Add login\n\nSigned-off-by: Alice"),
+                    commit("bbbbbbb", "fixup! API-1 Add login"),
+                    commit(
+                        "ccccccc",
+                        "API-2 Fix it\n\nSigned-off-by: Bob <bob@example.com>"
+                    ),
+                ]
+            ),
+            vec![
+                "feature has 3 commits, more than the 2 allowed",
+                "bbbbbbb \"fixup! API-1 Add login\" has to be squashed before merging",
+                "bbbbbbb \"fixup! API-1 Add login\" doesn't match the pattern ^[A-Z]+-[0-9]+ ",
+                "bbbbbbb \"fixup! API-1 Add login\" isn't signed off",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_freeze_violations() {
+        let policy = MergePolicy {
+            freezes: vec![parse_window("2026-12-20..2027-01-03").unwrap()],
+            freeze_branches: vec!["master".to_string()],
+            ..MergePolicy::default()
+        };
+        let day = |date: &str| NaiveDate::parse_from_str(date, "%Y-%m-%d").unwrap();
+
+        let into_master = PlannedMerge::new("feature", "master", true);
+        assert_eq!(
+            policy
+                .freeze_violations(&into_master, day("2027-01-03"))
+                .len(),
+            1
+        );
+        assert!(policy
+            .freeze_violations(&into_master, day("2027-01-04"))
+            .is_empty());
+
+        let into_staging = PlannedMerge::new("feature", "staging", false);
+        assert!(policy
+            .freeze_violations(&into_staging, day("2026-12-24"))
+            .is_empty());
+        assert_eq!(parse_window("2026-12-20"), None);
+    }
+}
@@ -0,0 +1,198 @@
+use crate::command_chain::{Risk, RunStepResult, Step};
+use crate::git::{open_repo, Git};
+use colored::*;
+use git2::{build::CheckoutBuilder, BranchType, Oid, Repository};
+
+/// A git command done through libgit2 instead of spawning `git`, used when `api-git.backend` is
+/// `native`. Only local operations are supported, other commands still run `git`.
+#[derive(Debug, PartialEq, Eq)]
+pub enum NativeGit {
+    /// `git checkout <branch>` of a local branch.
+    Checkout(String),
+    /// `git branch <name> [<start>]`
+    Branch(String, Option<String>),
+    /// `git merge --ff-only <branch>`
+    FastForwardMerge(String),
+    /// `git branch -D <branch>`
+    DeleteBranch(String),
+}
+
+impl NativeGit {
+    /// The native version of a git command, if there is one.
+    pub fn parse(command: &str, args: &[String]) -> Option<NativeGit> {
+        if command != "git" {
+            return None;
+        }
+
+        let args = args.iter().map(String::as_str).collect::<Vec<_>>();
+        let arg = |s: &str| s.to_string();
+
+        match args.as_slice() {
+            ["checkout", branch] if !branch.starts_with('-') => {
+                Some(NativeGit::Checkout(arg(branch)))
+            }
+            ["branch", name] if !name.starts_with('-') => Some(NativeGit::Branch(arg(name), None)),
+            ["branch", name, start] if !name.starts_with('-') => {
+                Some(NativeGit::Branch(arg(name), Some(arg(start))))
+            }
+            ["branch", "-D", branch] => Some(NativeGit::DeleteBranch(arg(branch))),
+            ["merge", "--ff-only", branch] => Some(NativeGit::FastForwardMerge(arg(branch))),
+            _ => None,
+        }
+    }
+
+    fn run(&self, repo: &Repository) -> Result<(), String> {
+        match self {
+            NativeGit::Checkout(branch) => checkout(repo, branch),
+            NativeGit::Branch(name, start) => {
+                let target = match start {
+                    Some(start) => resolve(repo, start)?,
+                    None => repo
+                        .head()
+                        .ok()
+                        .and_then(|head| head.target())
+                        .ok_or("HEAD doesn't point to a commit")?,
+                };
+                let commit = repo
+                    .find_commit(target)
+                    .map_err(|e| e.message().to_string())?;
+                repo.branch(name, &commit, false)
+                    .map_err(|e| e.message().to_string())?;
+                Ok(())
+            }
+            NativeGit::FastForwardMerge(branch) => fast_forward(repo, branch),
+            NativeGit::DeleteBranch(branch) => repo
+                .find_branch(branch, BranchType::Local)
+                .and_then(|mut branch| branch.delete())
+                .map_err(|e| e.message().to_string()),
+        }
+    }
+
+    fn as_git(&self) -> Git {
+        match self {
+            NativeGit::Checkout(branch) => Git::checkout(branch),
+            NativeGit::Branch(name, None) => Git::branch(name),
+            NativeGit::Branch(name, Some(start)) => Git::from(format!("branch {} {}", name, start)),
+            NativeGit::FastForwardMerge(branch) => Git::fast_forward_merge(branch),
+            NativeGit::DeleteBranch(branch) => Git::delete_branch(branch),
+        }
+    }
+}
+
+impl Step for NativeGit {
+    fn run_step(&self) -> RunStepResult {
+        let repo = open_repo();
+
+        // Let git handle the cases it's smarter about, like creating a tracking branch when
+        // checking out a branch that only exists on origin
+        if let NativeGit::Checkout(branch) = self {
+            if repo.find_branch(branch, BranchType::Local).is_err() {
+                return self.as_git().run_step();
+            }
+        }
+
+        match self.run(&repo) {
+            Ok(()) => RunStepResult::Ok,
+            Err(err) => {
+                eprintln!("{}", err.red());
+                RunStepResult::Err
+            }
+        }
+    }
+
+    fn as_string(&self) -> String {
+        self.as_git().as_string()
+    }
+
+    fn risk(&self) -> Risk {
+        self.as_git().risk()
+    }
+}
+
+fn checkout(repo: &Repository, branch: &str) -> Result<(), String> {
+    let refname = format!("refs/heads/{}", branch);
+    let target = resolve(repo, branch)?;
+    let tree = repo
+        .find_commit(target)
+        .and_then(|commit| commit.tree())
+        .map_err(|e| e.message().to_string())?;
+
+    repo.checkout_tree(tree.as_object(), Some(CheckoutBuilder::new().safe()))
+        .map_err(|e| e.message().to_string())?;
+    repo.set_head(&refname)
+        .map_err(|e| e.message().to_string())?;
+    println!("Switched to branch '{}'", branch);
+
+    Ok(())
+}
+
+fn fast_forward(repo: &Repository, branch: &str) -> Result<(), String> {
+    let other = resolve(repo, branch)?;
+    let mut head = repo.head().map_err(|e| e.message().to_string())?;
+    let current = head.target().ok_or("HEAD doesn't point to a commit")?;
+
+    if current == other || repo.graph_descendant_of(current, other).unwrap_or(false) {
+        println!("Already up to date.");
+        return Ok(());
+    }
+
+    if !repo.graph_descendant_of(other, current).unwrap_or(false) {
+        return Err(format!(
+            "Can't fast-forward to {}, it has diverged from HEAD",
+            branch
+        ));
+    }
+
+    let tree = repo
+        .find_commit(other)
+        .and_then(|commit| commit.tree())
+        .map_err(|e| e.message().to_string())?;
+    repo.checkout_tree(tree.as_object(), Some(CheckoutBuilder::new().safe()))
+        .map_err(|e| e.message().to_string())?;
+    head.set_target(other, &format!("merge {}: Fast-forward", branch))
+        .map_err(|e| e.message().to_string())?;
+    println!("Fast-forward {}..{}", short(current), short(other));
+
+    Ok(())
+}
+
+fn resolve(repo: &Repository, rev: &str) -> Result<Oid, String> {
+    repo.revparse_single(rev)
+        .and_then(|object| object.peel_to_commit())
+        .map(|commit| commit.id())
+        .map_err(|e| e.message().to_string())
+}
+
+fn short(oid: Oid) -> String {
+    oid.to_string()[..7].to_string()
+}
+
+#[cfg(test)]
+mod test {
+    #[allow(unused_imports)]
+    use super::*;
+
+    fn parse(s: &str) -> Option<NativeGit> {
+        let git = Git::from(s);
+        let args = crate::command::Command::args(&git);
+        NativeGit::parse("git", &args)
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            parse("checkout master"),
+            Some(NativeGit::Checkout("master".into()))
+        );
+        assert_eq!(
+            parse("merge --ff-only feature"),
+            Some(NativeGit::FastForwardMerge("feature".into()))
+        );
+        assert_eq!(
+            parse("branch -D feature"),
+            Some(NativeGit::DeleteBranch("feature".into()))
+        );
+        assert_eq!(parse("checkout --detach abc123"), None);
+        assert_eq!(parse("push origin master"), None);
+    }
+}
@@ -0,0 +1,210 @@
+use crate::command_chain::{CommandChain, Risk};
+use crate::git::resolve_branch;
+use crate::string_ext::*;
+use colored::*;
+use git2::{Repository, Sort};
+
+/// How many commits are listed before the rest are summed up.
+const MAX_COMMITS: usize = 20;
+
+/// A merge a command will do, described before asking for confirmation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlannedMerge {
+    pub branch: String,
+    pub into: String,
+    /// Whether the branch is rebased onto `into` before merging.
+    pub rebase: bool,
+}
+
+impl PlannedMerge {
+    pub fn new(branch: &str, into: &str, rebase: bool) -> PlannedMerge {
+        PlannedMerge {
+            branch: branch.to_string(),
+            into: into.to_string(),
+            rebase,
+        }
+    }
+}
+
+/// What a merge brings in, from the refs as they are before fetching.
+#[derive(Debug)]
+struct MergePreview {
+    /// Short SHA, subject and author of the commits on the branch that aren't in the target.
+    commits: Vec<(String, String, String)>,
+    files_changed: usize,
+    insertions: usize,
+    deletions: usize,
+    /// The number of commits on the target that aren't on the branch.
+    behind: usize,
+}
+
+impl MergePreview {
+    fn new(repo: &Repository, merge: &PlannedMerge) -> Result<MergePreview, git2::Error> {
+        let branch = resolve_branch(repo, &merge.branch)?;
+        let into = resolve_branch(repo, &merge.into)?;
+
+        let mut walk = repo.revwalk()?;
+        walk.set_sorting(Sort::TOPOLOGICAL);
+        walk.push(branch)?;
+        walk.hide(into)?;
+        let commits = walk
+            .map(|oid| {
+                let commit = repo.find_commit(oid?)?;
+                let author = commit.author().name().unwrap_or("").to_string();
+                Ok((
+                    commit.id().to_string()[..7].to_string(),
+                    commit.summary().unwrap_or("").to_string(),
+                    author,
+                ))
+            })
+            .collect::<Result<Vec<_>, git2::Error>>()?;
+
+        let base = repo.merge_base(branch, into)?;
+        let diff = repo.diff_tree_to_tree(
+            Some(&repo.find_commit(base)?.tree()?),
+            Some(&repo.find_commit(branch)?.tree()?),
+            None,
+        )?;
+        let stats = diff.stats()?;
+
+        let (_, behind) = repo.graph_ahead_behind(branch, into)?;
+
+        Ok(MergePreview {
+            commits,
+            files_changed: stats.files_changed(),
+            insertions: stats.insertions(),
+            deletions: stats.deletions(),
+            behind,
+        })
+    }
+}
+
+/// Print the merges and the steps of `chain` from `from_step`, so they can be checked before
+/// confirming. Steps that aren't local are marked with their risk.
+pub fn print_plan(merges: &[PlannedMerge], chain: &CommandChain, from_step: usize) {
+    let repo = Repository::open(".").ok();
+
+    for merge in merges {
+        println!(
+            "{}",
+            format!("Merging {} into {}", merge.branch, merge.into).bold()
+        );
+
+        let preview = match repo.as_ref().map(|repo| MergePreview::new(repo, merge)) {
+            Some(Ok(preview)) => preview,
+            Some(Err(err)) => {
+                println!(
+                    "{}",
+                    format!("Can't preview the merge: {}", err.message()).indent(2)
+                );
+                println!();
+                continue;
+            }
+            None => continue,
+        };
+
+        println!("{}", describe(merge, &preview).indent(2));
+        println!();
+    }
+
+    println!("{}", "Steps:".bold());
+    for (step, (command, risk)) in chain.plan().iter().enumerate().skip(from_step) {
+        let label = format!("[{}]", risk.as_str());
+        match risk {
+            Risk::Local => println!("  {:>2}  {}", step, command),
+            Risk::RemoteWrite => println!("  {:>2}  {}  {}", step, command, label.yellow()),
+            Risk::Destructive | Risk::Deploy => {
+                println!("  {:>2}  {}  {}", step, command, label.red().bold())
+            }
+        }
+    }
+    println!();
+}
+
+fn describe(merge: &PlannedMerge, preview: &MergePreview) -> String {
+    let mut lines = vec![];
+
+    match preview.commits.len() {
+        0 => lines.push(format!("No commits that aren't in {} already", merge.into)),
+        1 => lines.push("1 commit:".to_string()),
+        n => lines.push(format!("{} commits:", n)),
+    }
+    for (sha, subject, author) in preview.commits.iter().take(MAX_COMMITS) {
+        lines.push(format!("  {} {} ({})", sha.yellow(), subject, author));
+    }
+    if preview.commits.len() > MAX_COMMITS {
+        lines.push(format!(
+            "  ... and {} more",
+            preview.commits.len() - MAX_COMMITS
+        ));
+    }
+
+    lines.push(format!(
+        "{} file{} changed, {} insertion{}(+), {} deletion{}(-)",
+        preview.files_changed,
+        plural(preview.files_changed),
+        preview.insertions,
+        plural(preview.insertions),
+        preview.deletions,
+        plural(preview.deletions),
+    ));
+
+    lines.push(match (preview.behind, merge.rebase) {
+        (0, _) => format!(
+            "{} is up to date with {}, no rebase needed",
+            merge.branch, merge.into
+        ),
+        (behind, true) => format!(
+            "{} is {} commit{} behind {} and will be rebased onto it",
+            merge.branch,
+            behind,
+            plural(behind),
+            merge.into
+        ),
+        (behind, false) => format!(
+            "{} is {} commit{} behind {}, it's merged without rebasing",
+            merge.branch,
+            behind,
+            plural(behind),
+            merge.into
+        ),
+    });
+
+    lines.join("\n")
+}
+
+fn plural(n: usize) -> &'static str {
+    if n == 1 {
+        ""
+    } else {
+        "s"
+    }
+}
+
+#[cfg(test)]
+mod test {
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[test]
+    fn test_describe() {
+        colored::control::set_override(false);
+
+        let preview = MergePreview {
+            commits: vec![(
+                "abc1234".into(),
+                "Add login page".into(),
+                "Alice Smith".into(),
+            )],
+            files_changed: 2,
+            insertions: 1,
+            deletions: 0,
+            behind: 3,
+        };
+
+        assert_eq!(
+            describe(&PlannedMerge::new("feature", "master", true), &preview),
+            "1 commit:\n  abc1234 Add login page (Alice Smith)\n2 files changed, 1 insertion(+), 0 deletions(-)\nfeature is 3 commits behind master and will be rebased onto it"
+        );
+    }
+}
@@ -0,0 +1,317 @@
+use crate::branch_update::FastForwardFromRemote;
+use crate::command_chain::{CommandChain, RunStepResult, Step};
+use crate::config::Config;
+use crate::git::{open_repo, Git};
+use crate::hooks::{HookPoint, Hooks};
+use git2::BranchType;
+use regex::Regex;
+use std::collections::HashSet;
+use std::fmt;
+
+/// Used when `api-git.propagate` isn't configured.
+const DEFAULT_GRAPH: &str = "master -> staging, develop";
+
+/// Which branches get merged into which after a branch has been updated.
+///
+/// Configured with `api-git.propagate`, which can be given multiple times:
+///
+/// ```text
+/// [api-git]
+///     propagate = master -> staging, develop:skip-diverged
+///     propagate = release-* -> master:ff-only
+/// ```
+///
+/// The source may contain `*` wildcards. Each target can pick a strategy: `merge` (the default),
+/// `ff-only`, or `skip-diverged` which fast-forwards if possible and otherwise leaves the
+/// branch alone.
+#[derive(Debug)]
+pub struct PropagationGraph {
+    rules: Vec<Rule>,
+}
+
+#[derive(Debug)]
+struct Rule {
+    source: Regex,
+    into: String,
+    strategy: Strategy,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Strategy {
+    Merge,
+    FastForwardOnly,
+    SkipDiverged,
+}
+
+/// Merging one branch into another.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Edge {
+    pub from: String,
+    pub into: String,
+    pub strategy: Strategy,
+}
+
+impl PropagationGraph {
+    pub fn from_config(config: &Config) -> Result<PropagationGraph, String> {
+        let mut lines = config.get_all("api-git.propagate");
+        if lines.is_empty() {
+            lines.push(DEFAULT_GRAPH.to_string());
+        }
+
+        PropagationGraph::parse(&lines).map_err(|err| format!("Invalid api-git.propagate: {}", err))
+    }
+
+    pub fn parse(lines: &[String]) -> Result<PropagationGraph, String> {
+        let mut rules = vec![];
+
+        for line in lines {
+            let mut parts = line.splitn(2, "->");
+            let source = parts.next().unwrap_or("").trim();
+            let targets = parts
+                .next()
+                .ok_or_else(|| format!("expected `<branch> -> <branches>` but got `{}`", line))?;
+
+            for target in targets.split(',').map(str::trim).filter(|t| !t.is_empty()) {
+                let mut target_parts = target.splitn(2, ':');
+                let into = target_parts.next().unwrap_or("").trim().to_string();
+                let strategy = match target_parts.next().map(str::trim) {
+                    None | Some("merge") => Strategy::Merge,
+                    Some("ff-only") => Strategy::FastForwardOnly,
+                    Some("skip-diverged") => Strategy::SkipDiverged,
+                    Some(other) => return Err(format!("unknown strategy `{}`", other)),
+                };
+
+                rules.push(Rule {
+                    source: glob(source),
+                    into,
+                    strategy,
+                });
+            }
+        }
+
+        Ok(PropagationGraph { rules })
+    }
+
+    /// The merges to do after `branch` has been updated, including the ones needed because a
+    /// downstream branch was updated. Sorted so a branch is merged into others only after
+    /// everything that flows into it has been merged.
+    pub fn edges_from(&self, branch: &str) -> Result<Vec<Edge>, String> {
+        let mut edges = vec![];
+        let mut visited = HashSet::new();
+        let mut queue = vec![branch.to_string()];
+
+        while let Some(from) = queue.pop() {
+            if !visited.insert(from.clone()) {
+                continue;
+            }
+
+            for rule in self.rules.iter().filter(|rule| rule.source.is_match(&from)) {
+                if rule.into == from {
+                    continue;
+                }
+                queue.push(rule.into.clone());
+                edges.push(Edge {
+                    from: from.clone(),
+                    into: rule.into.clone(),
+                    strategy: rule.strategy,
+                });
+            }
+        }
+
+        let order = topological_order(branch, &edges)?;
+        edges.sort_by_key(|edge| order.iter().position(|b| b == &edge.from));
+        Ok(edges)
+    }
+}
+
+impl Edge {
+    /// Add the steps that merge `from` into `into`, with their hooks. Pushing is left to the
+    /// caller.
+    pub fn add_steps(&self, c: &mut CommandChain, hooks: &Hooks) {
+        c.add(FastForwardFromRemote::new(&self.into));
+        c.add(Git::checkout(&self.into));
+
+        let (from, into) = (Some(self.from.as_str()), Some(self.into.as_str()));
+        hooks.add(c, HookPoint::BeforeMerge, from, into);
+
+        match self.strategy {
+            Strategy::Merge => c.add(Git::merge(&self.from)),
+            Strategy::FastForwardOnly => c.add(Git::fast_forward_merge(&self.from)),
+            Strategy::SkipDiverged => c.add(FastForwardUnlessDiverged {
+                branch: self.from.clone(),
+            }),
+        }
+
+        hooks.add(c, HookPoint::AfterMerge, from, into);
+    }
+
+    /// The edges that update `from`, which have to succeed before this one runs.
+    pub fn after(&self, edges: &[Edge]) -> Vec<String> {
+        edges
+            .iter()
+            .filter(|edge| edge.into == self.from)
+            .map(ToString::to_string)
+            .collect()
+    }
+}
+
+impl fmt::Display for Edge {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} into {}", self.from, self.into)
+    }
+}
+
+/// Propagation edges from `branch`, leaving out targets that don't exist either locally or on
+/// origin. Fails if the graph is invalid or has a cycle.
+pub fn propagation_edges(branch: &str) -> Result<Vec<Edge>, String> {
+    let graph = PropagationGraph::from_config(&Config::load()?)?;
+
+    match graph.edges_from(branch) {
+        Ok(edges) => Ok(edges
+            .into_iter()
+            .filter(|edge| branch_exists_anywhere(&edge.into))
+            .collect()),
+        Err(err) => Err(format!("Invalid api-git.propagate: {}", err)),
+    }
+}
+
+/// Fast-forward the current branch to `branch`, or do nothing if they have diverged.
+struct FastForwardUnlessDiverged {
+    branch: String,
+}
+
+impl Step for FastForwardUnlessDiverged {
+    fn run_step(&self) -> RunStepResult {
+        let repo = open_repo();
+        let head = repo.head().ok().and_then(|head| head.target());
+        let other = repo
+            .refname_to_id(&format!("refs/heads/{}", self.branch))
+            .ok();
+
+        if let (Some(head), Some(other)) = (head, other) {
+            let can_fast_forward =
+                head == other || repo.graph_descendant_of(other, head).unwrap_or(false);
+            if !can_fast_forward {
+                println!("Diverged from {}, skipping", self.branch);
+                return RunStepResult::Ok;
+            }
+        }
+
+        Git::fast_forward_merge(&self.branch).run_step()
+    }
+
+    fn as_string(&self) -> String {
+        format!(
+            "{} # unless diverged",
+            Git::fast_forward_merge(&self.branch).as_string()
+        )
+    }
+}
+
+fn topological_order(start: &str, edges: &[Edge]) -> Result<Vec<String>, String> {
+    let mut order = vec![];
+    let mut remaining = edges.iter().collect::<Vec<_>>();
+    let mut ready = vec![start.to_string()];
+
+    while let Some(branch) = ready.pop() {
+        order.push(branch.clone());
+
+        let (done, rest): (Vec<_>, Vec<_>) =
+            remaining.into_iter().partition(|edge| edge.from == branch);
+        remaining = rest;
+
+        for edge in done {
+            if order.contains(&edge.into) {
+                return Err(format!(
+                    "there is a cycle involving {} and {}",
+                    edge.from, edge.into
+                ));
+            }
+
+            let has_other_sources = remaining.iter().any(|other| other.into == edge.into);
+            if !has_other_sources && !ready.contains(&edge.into) {
+                ready.push(edge.into.clone());
+            }
+        }
+    }
+
+    match remaining.first() {
+        None => Ok(order),
+        Some(edge) => Err(format!(
+            "there is a cycle involving {} and {}",
+            edge.from, edge.into
+        )),
+    }
+}
+
+fn branch_exists_anywhere(branch: &str) -> bool {
+    let repo = open_repo();
+    repo.find_branch(branch, BranchType::Local).is_ok()
+        || repo
+            .find_branch(&format!("origin/{}", branch), BranchType::Remote)
+            .is_ok()
+}
+
+fn glob(pattern: &str) -> Regex {
+    let pattern = regex::escape(pattern).replace("\\*", ".*");
+    Regex::new(&format!("^{}$", pattern)).expect("invalid glob")
+}
+
+#[cfg(test)]
+mod test {
+    #[allow(unused_imports)]
+    use super::*;
+
+    fn edges(lines: &[&str], branch: &str) -> Result<Vec<(String, String)>, String> {
+        let lines = lines.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        let graph = PropagationGraph::parse(&lines)?;
+        Ok(graph
+            .edges_from(branch)?
+            .into_iter()
+            .map(|edge| (edge.from, edge.into))
+            .collect())
+    }
+
+    fn edge(from: &str, into: &str) -> (String, String) {
+        (from.to_string(), into.to_string())
+    }
+
+    #[test]
+    fn test_multi_hop_propagation() {
+        let lines = ["release-* -> master:ff-only", "master -> staging, develop"];
+
+        assert_eq!(
+            edges(&lines, "release-2").unwrap(),
+            vec![
+                edge("release-2", "master"),
+                edge("master", "staging"),
+                edge("master", "develop"),
+            ],
+        );
+        assert_eq!(edges(&lines, "staging").unwrap(), vec![]);
+    }
+
+    #[test]
+    fn test_branch_merged_after_all_its_sources() {
+        let lines = ["master -> develop, staging", "staging -> develop"];
+
+        assert_eq!(
+            edges(&lines, "master").unwrap(),
+            vec![
+                edge("master", "develop"),
+                edge("master", "staging"),
+                edge("staging", "develop"),
+            ],
+        );
+    }
+
+    #[test]
+    fn test_cycles_are_rejected() {
+        assert!(edges(&["master -> staging", "staging -> master"], "master").is_err());
+    }
+
+    #[test]
+    fn test_unknown_strategies_are_rejected() {
+        assert!(edges(&["master -> staging:rebase"], "master").is_err());
+    }
+}
@@ -0,0 +1,138 @@
+use colored::*;
+use git2::{Oid, Repository};
+use std::process;
+
+/// State shared with the team through refs on origin, like freezes and deploy locks. Each ref
+/// points at a commit with an empty tree. Its author is who created it and its message has the
+/// details.
+pub struct RemoteRef {
+    pub name: String,
+    pub oid: Oid,
+    pub author: String,
+    pub email: String,
+    pub time: git2::Time,
+    pub message: String,
+}
+
+impl RemoteRef {
+    /// The message without the subject line.
+    pub fn body(&self) -> &str {
+        self.message
+            .split_once("\n\n")
+            .map(|(_, body)| body.trim())
+            .unwrap_or("")
+    }
+
+    /// Whether the current git user created it.
+    pub fn is_mine(&self, repo: &Repository) -> bool {
+        repo.signature()
+            .ok()
+            .and_then(|signature| signature.email().map(ToString::to_string))
+            .is_some_and(|email| email == self.email)
+    }
+}
+
+/// Update the local refs under `prefix` from origin. If that fails the refs fetched before are
+/// used.
+pub fn fetch(prefix: &str, what: &str) {
+    fetch_into(prefix, prefix, what);
+}
+
+/// Fetch the refs under `prefix` on origin to the same names under `local_prefix`. Returns
+/// whether it worked, if it didn't the refs fetched before are used.
+pub fn fetch_into(prefix: &str, local_prefix: &str, what: &str) -> bool {
+    let refspec = format!("+{}*:{}*", prefix, local_prefix);
+    let fetched = process::Command::new("git")
+        .args(["fetch", "origin", "--prune", "--quiet", &refspec])
+        .status()
+        .is_ok_and(|status| status.success());
+
+    if !fetched {
+        eprintln!(
+            "{}",
+            format!(
+                "Couldn't fetch the {} from origin, using the ones fetched before",
+                what
+            )
+            .yellow()
+        );
+    }
+    fetched
+}
+
+/// Read a ref fetched with `fetch`.
+pub fn find(repo: &Repository, name: &str) -> Option<RemoteRef> {
+    let reference = repo.find_reference(name).ok()?;
+    let commit = reference.peel_to_commit().ok()?;
+    let author = commit.author();
+
+    Some(RemoteRef {
+        name: name.to_string(),
+        oid: commit.id(),
+        author: author.name().unwrap_or("").to_string(),
+        email: author.email().unwrap_or("").to_string(),
+        time: author.when(),
+        message: commit.message().unwrap_or("").to_string(),
+    })
+}
+
+/// Every ref under `prefix`, sorted by name.
+pub fn find_all(repo: &Repository, prefix: &str) -> Vec<RemoteRef> {
+    let mut names = repo
+        .references_glob(&format!("{}*", prefix))
+        .map(|references| {
+            references
+                .filter_map(Result::ok)
+                .filter_map(|reference| reference.name().map(ToString::to_string))
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    names.sort();
+
+    names.iter().filter_map(|name| find(repo, name)).collect()
+}
+
+/// Create the commit a ref points at, authored by the current git user.
+pub fn create_commit(repo: &Repository, message: &str) -> Result<Oid, git2::Error> {
+    let tree = repo.find_tree(repo.treebuilder(None)?.write()?)?;
+    let signature = repo.signature()?;
+    repo.commit(None, &signature, &signature, message, &tree, &[])
+}
+
+/// Point `name` on origin at `new`, or delete it when `new` is `None`, but only if it's still
+/// at `expected` (or doesn't exist when `expected` is `None`). Updates the local ref too.
+pub fn compare_and_swap(
+    repo: &Repository,
+    name: &str,
+    expected: Option<Oid>,
+    new: Option<Oid>,
+) -> bool {
+    let lease = format!(
+        "--force-with-lease={}:{}",
+        name,
+        expected.map(|oid| oid.to_string()).unwrap_or_default()
+    );
+    let refspec = format!(
+        "{}:{}",
+        new.map(|oid| oid.to_string()).unwrap_or_default(),
+        name
+    );
+
+    let pushed = process::Command::new("git")
+        .args(["push", "--quiet", &lease, "origin", &refspec])
+        .status()
+        .is_ok_and(|status| status.success());
+
+    if pushed {
+        let updated = match new {
+            Some(oid) => repo.reference(name, oid, true, "api-git").map(|_| ()),
+            None => repo
+                .find_reference(name)
+                .and_then(|mut reference| reference.delete()),
+        };
+        if let Err(err) = updated {
+            eprintln!("Failed to update {}: {}", name, err.message());
+        }
+    }
+    pushed
+}
@@ -0,0 +1,273 @@
+use crate::command_chain::RunStepResult;
+use crate::git::open_repo;
+use colored::*;
+use git2::Oid;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::time::Duration;
+
+/// What happened during a run of a `CommandChain`.
+pub struct Report {
+    pub command: String,
+    pub result: RunStepResult,
+    pub steps: Vec<StepRecord>,
+    pub refs_before: RefState,
+    pub refs_after: RefState,
+    pub duration: Duration,
+}
+
+pub struct StepRecord {
+    pub index: usize,
+    pub command: String,
+    pub status: StepStatus,
+    pub duration: Duration,
+    pub deploy: bool,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum StepStatus {
+    Succeeded,
+    Failed,
+    /// Skipped when resuming, or because the unit it belongs to failed.
+    Skipped,
+    /// Not reached because an earlier step failed.
+    NotRun,
+    DryRun,
+}
+
+/// The SHAs of all local and remote-tracking branches, keyed by their short name.
+#[derive(Default)]
+pub struct RefState(BTreeMap<String, Oid>);
+
+/// A ref that points somewhere else after the run.
+pub struct RefMove {
+    pub name: String,
+    pub old: Oid,
+    pub new: Oid,
+    pub added: usize,
+    pub removed: usize,
+}
+
+impl RefState {
+    pub fn capture() -> RefState {
+        let repo = open_repo();
+        let mut refs = BTreeMap::new();
+
+        if let Ok(references) = repo.references() {
+            for reference in references.filter_map(Result::ok) {
+                if !(reference.is_branch() || reference.is_remote()) {
+                    continue;
+                }
+                if let (Some(name), Some(oid)) = (reference.shorthand(), reference.target()) {
+                    refs.insert(name.to_string(), oid);
+                }
+            }
+        }
+
+        RefState(refs)
+    }
+
+    pub fn to_strings(&self) -> BTreeMap<String, String> {
+        self.0
+            .iter()
+            .map(|(name, oid)| (name.clone(), oid.to_string()))
+            .collect()
+    }
+}
+
+impl StepStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            StepStatus::Succeeded => "ok",
+            StepStatus::Failed => "failed",
+            StepStatus::Skipped => "skipped",
+            StepStatus::NotRun => "not run",
+            StepStatus::DryRun => "dry run",
+        }
+    }
+}
+
+impl Report {
+    /// Whether every step that ran succeeded, including the ones in units.
+    pub fn succeeded(&self) -> bool {
+        if let RunStepResult::Err = self.result {
+            return false;
+        }
+        self.steps
+            .iter()
+            .all(|step| step.status != StepStatus::Failed)
+    }
+
+    pub fn moved_refs(&self) -> Vec<RefMove> {
+        let repo = open_repo();
+
+        self.refs_after
+            .0
+            .iter()
+            .filter_map(|(name, new)| {
+                let old = self.refs_before.0.get(name)?;
+                if old == new {
+                    return None;
+                }
+                let (added, removed) = repo.graph_ahead_behind(*new, *old).unwrap_or((0, 0));
+                Some(RefMove {
+                    name: name.clone(),
+                    old: *old,
+                    new: *new,
+                    added,
+                    removed,
+                })
+            })
+            .collect()
+    }
+
+    pub fn created_refs(&self) -> Vec<&str> {
+        self.refs_after
+            .0
+            .keys()
+            .filter(|name| !self.refs_before.0.contains_key(*name))
+            .map(|name| name.as_str())
+            .collect()
+    }
+
+    pub fn deleted_refs(&self) -> Vec<&str> {
+        self.refs_before
+            .0
+            .keys()
+            .filter(|name| !self.refs_after.0.contains_key(*name))
+            .map(|name| name.as_str())
+            .collect()
+    }
+
+    pub fn deploys(&self) -> Vec<&StepRecord> {
+        self.steps
+            .iter()
+            .filter(|step| step.deploy && step.status == StepStatus::Succeeded)
+            .collect()
+    }
+
+    pub fn print(&self) {
+        println!();
+        println!("{}", "Report:".bold());
+
+        for step in &self.steps {
+            let status = format!("{:8}", step.status.as_str());
+            let status = match step.status {
+                StepStatus::Succeeded | StepStatus::DryRun => status.green(),
+                StepStatus::Failed => status.red(),
+                StepStatus::Skipped | StepStatus::NotRun => status.yellow(),
+            };
+            println!(
+                "  {:>3}  {}  {:>6}  {}",
+                step.index,
+                status,
+                format_duration(step.duration),
+                step.command
+            );
+        }
+
+        let moved = self.moved_refs();
+        if !moved.is_empty() {
+            println!();
+            for ref_move in moved {
+                println!("  {}  {}", ref_move.name.bold(), describe_move(&ref_move));
+            }
+        }
+
+        let created = self.created_refs();
+        if !created.is_empty() {
+            println!("  Created: {}", created.join(", "));
+        }
+
+        let deleted = self.deleted_refs();
+        if !deleted.is_empty() {
+            println!("  Deleted: {}", deleted.join(", "));
+        }
+
+        for deploy in self.deploys() {
+            println!("  Deployed: {}", deploy.command);
+        }
+
+        println!("  Took {}", format_duration(self.duration));
+    }
+
+    pub fn to_markdown(&self) -> String {
+        let mut md = String::new();
+
+        writeln!(md, "## `api-git {}`", self.command).unwrap();
+        writeln!(md).unwrap();
+        writeln!(md, "| Step | Status | Duration | Command |").unwrap();
+        writeln!(md, "| ---: | --- | ---: | --- |").unwrap();
+        for step in &self.steps {
+            writeln!(
+                md,
+                "| {} | {} | {} | `{}` |",
+                step.index,
+                step.status.as_str(),
+                format_duration(step.duration),
+                step.command
+            )
+            .unwrap();
+        }
+
+        let moved = self.moved_refs();
+        if !moved.is_empty() {
+            writeln!(md).unwrap();
+            writeln!(md, "### Refs").unwrap();
+            writeln!(md).unwrap();
+            for ref_move in moved {
+                writeln!(md, "- `{}`: {}", ref_move.name, describe_move(&ref_move)).unwrap();
+            }
+        }
+
+        let created = self.created_refs();
+        let deleted = self.deleted_refs();
+        if !created.is_empty() || !deleted.is_empty() {
+            writeln!(md).unwrap();
+            writeln!(md, "### Branches").unwrap();
+            writeln!(md).unwrap();
+            for name in created {
+                writeln!(md, "- Created `{}`", name).unwrap();
+            }
+            for name in deleted {
+                writeln!(md, "- Deleted `{}`", name).unwrap();
+            }
+        }
+
+        let deploys = self.deploys();
+        if !deploys.is_empty() {
+            writeln!(md).unwrap();
+            writeln!(md, "### Deploys").unwrap();
+            writeln!(md).unwrap();
+            for deploy in deploys {
+                writeln!(md, "- `{}`", deploy.command).unwrap();
+            }
+        }
+
+        writeln!(md).unwrap();
+        writeln!(md, "Took {}", format_duration(self.duration)).unwrap();
+
+        md
+    }
+}
+
+fn describe_move(ref_move: &RefMove) -> String {
+    let old = ref_move.old.to_string();
+    let new = ref_move.new.to_string();
+    let mut description = format!("{}..{} (+{}", &old[..7], &new[..7], ref_move.added);
+    if ref_move.removed > 0 {
+        description.push_str(&format!(", -{}", ref_move.removed));
+    }
+    description.push_str(if ref_move.added == 1 && ref_move.removed == 0 {
+        " commit)"
+    } else {
+        " commits)"
+    });
+    description
+}
+
+fn format_duration(duration: Duration) -> String {
+    format!("{:.1}s", duration.as_secs_f64())
+}
@@ -0,0 +1,75 @@
+use crate::command_chain::{RunStepResult, Step};
+use crate::git::{branch_exists, open_repo, Git};
+use crate::journal::Journal;
+use colored::*;
+use git2::RepositoryState;
+
+/// Check out the branch, or detached commit, that was checked out when the run started.
+pub struct ReturnToStart {
+    branch: Option<String>,
+    head: Option<String>,
+}
+
+impl ReturnToStart {
+    pub fn new(journal: &Journal) -> ReturnToStart {
+        ReturnToStart {
+            branch: journal.original_branch.clone(),
+            head: journal.original_head.clone(),
+        }
+    }
+
+    fn checkout(&self) -> Option<Git> {
+        match (&self.branch, &self.head) {
+            (Some(branch), _) => Some(Git::checkout(branch)),
+            (None, Some(head)) => Some(Git::from(format!("checkout --detach {}", head))),
+            (None, None) => None,
+        }
+    }
+}
+
+impl Step for ReturnToStart {
+    fn run_step(&self) -> RunStepResult {
+        if let Some(branch) = &self.branch {
+            if !branch_exists(branch) {
+                println!(
+                    "{}",
+                    format!("{} no longer exists, staying where we are", branch).yellow()
+                );
+                return RunStepResult::Ok;
+            }
+        }
+
+        match self.checkout() {
+            Some(git) => git.run_step(),
+            None => RunStepResult::Ok,
+        }
+    }
+
+    fn as_string(&self) -> String {
+        match self.checkout() {
+            Some(git) => git.as_string(),
+            None => "# nothing to return to".to_string(),
+        }
+    }
+}
+
+/// After a failed step, go back to where the run started unless a conflict has to be resolved
+/// where it happened.
+pub fn restore_after_failure(journal: &Journal) {
+    let state = open_repo().state();
+    if state != RepositoryState::Clean {
+        println!(
+            "{}",
+            format!(
+                "Not returning to the starting branch since a {:?} is in progress. Resolve it first",
+                state
+            )
+            .yellow()
+        );
+        return;
+    }
+
+    let step = ReturnToStart::new(journal);
+    println!("{}", format!("-- Returning: {}", step.as_string()).green());
+    step.run_step();
+}
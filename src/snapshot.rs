@@ -0,0 +1,108 @@
+use crate::command::Command;
+use crate::git::{head_branch, open_repo, Git};
+use git2::{BranchType, Oid, RepositoryState};
+use std::collections::HashMap;
+
+/// The state of the local branches and HEAD, so a group of steps can be undone.
+pub struct Snapshot {
+    head_branch: Option<String>,
+    head: Option<Oid>,
+    branches: HashMap<String, Oid>,
+}
+
+impl Snapshot {
+    pub fn take() -> Snapshot {
+        let repo = open_repo();
+
+        Snapshot {
+            head_branch: head_branch(),
+            head: repo.head().ok().and_then(|head| head.target()),
+            branches: local_branches(),
+        }
+    }
+
+    /// Abort any merge or rebase in progress, and put HEAD and the local branches back where
+    /// they were. Branches created since the snapshot are deleted.
+    pub fn restore(&self) -> Result<(), String> {
+        let abort = match open_repo().state() {
+            RepositoryState::Merge => Some("merge --abort"),
+            RepositoryState::Rebase
+            | RepositoryState::RebaseInteractive
+            | RepositoryState::RebaseMerge => Some("rebase --abort"),
+            RepositoryState::CherryPick | RepositoryState::CherryPickSequence => {
+                Some("cherry-pick --abort")
+            }
+            RepositoryState::Revert | RepositoryState::RevertSequence => Some("revert --abort"),
+            _ => None,
+        };
+        if let Some(abort) = abort {
+            run(Git::from(abort))?;
+        }
+
+        match (&self.head_branch, self.head) {
+            (Some(branch), _) => run(Git::checkout(branch))?,
+            (None, Some(head)) => run(Git::from(format!("checkout --detach {}", head)))?,
+            (None, None) => {}
+        }
+
+        let repo = open_repo();
+        let current = local_branches();
+
+        for (branch, oid) in &self.branches {
+            if current.get(branch) == Some(oid) {
+                continue;
+            }
+
+            if Some(branch) == self.head_branch.as_ref() {
+                run(Git::from(format!("reset --hard {}", oid)))?;
+            } else {
+                repo.reference(
+                    &format!("refs/heads/{}", branch),
+                    *oid,
+                    true,
+                    "api-git: roll back",
+                )
+                .map_err(|e| e.to_string())?;
+            }
+        }
+
+        for branch in current.keys() {
+            if !self.branches.contains_key(branch) {
+                let mut branch = repo
+                    .find_branch(branch, BranchType::Local)
+                    .map_err(|e| e.to_string())?;
+                branch.delete().map_err(|e| e.to_string())?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn local_branches() -> HashMap<String, Oid> {
+    let repo = open_repo();
+    let branches = repo
+        .branches(Some(BranchType::Local))
+        .expect("get branches");
+
+    branches
+        .filter_map(|branch| {
+            let (branch, _branch_type) = branch.ok()?;
+            let name = branch.name().ok()??.to_string();
+            let oid = branch.get().target()?;
+            Some((name, oid))
+        })
+        .collect()
+}
+
+fn run(git: Git) -> Result<(), String> {
+    if git.execute().status.success() {
+        Ok(())
+    } else {
+        Err(format!(
+            "`{} {}` failed",
+            git.command(),
+            git.args().join(" ")
+        ))
+    }
+}
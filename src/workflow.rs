@@ -0,0 +1,426 @@
+use crate::command_chain::{CommandChain, RunStepResult, Step};
+use crate::config::Config;
+use crate::git::{confirm, ConfirmDefault, Git};
+use regex::{Captures, Regex};
+use std::collections::HashMap;
+
+/// Subcommands that workflows can't replace.
+const BUILT_IN: &[&str] = &[
+    "start",
+    "merge",
+    "ship-hotfix",
+    "on-staging",
+    "on-develop",
+    "restore-branch",
+    "history",
+    "freeze",
+    "lock",
+    "deploys",
+    "help",
+];
+
+/// Options every workflow gets, which its own arguments can't be named like.
+const RESERVED_ARGS: &[&str] = &[
+    "dry-run",
+    "from-step",
+    "autostash",
+    "restore-branch",
+    "report",
+    "confirm",
+    "allow-destructive",
+    "yes",
+    "no-input",
+    "help",
+    "version",
+];
+
+/// A subcommand defined in the config as a list of steps.
+///
+/// ```text
+/// [api-git-workflow "release"]
+///     about = Cut a release branch
+///     arg = VERSION
+///     flag = base=master
+///     step = checkout {base}
+///     step = pull
+///     step = branch release-{VERSION}
+///     step = checkout release-{VERSION}
+///     step = confirm Push release-{VERSION}
+///     step = push release-{VERSION}
+/// ```
+///
+/// `arg` adds a required positional argument and `flag` an option taking a value, with an
+/// optional default. Both can be used in steps as `{NAME}`. Their values are passed to git as
+/// single arguments and quoted in `run` steps.
+#[derive(Debug)]
+pub struct Workflow {
+    pub name: String,
+    pub about: Option<String>,
+    pub args: Vec<String>,
+    pub flags: Vec<Flag>,
+    steps: Vec<StepTemplate>,
+}
+
+#[derive(Debug)]
+pub struct Flag {
+    pub name: String,
+    pub default: Option<String>,
+}
+
+#[derive(Debug)]
+struct StepTemplate {
+    kind: StepKind,
+    arg: String,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum StepKind {
+    Checkout,
+    Pull,
+    Fetch,
+    Branch,
+    Merge,
+    FastForwardMerge,
+    Rebase,
+    Push,
+    Run,
+    Confirm,
+}
+
+impl Workflow {
+    /// All workflows in the config. Invalid ones are reported and left out.
+    pub fn load_all(config: &Config) -> Vec<Workflow> {
+        config
+            .subsections("api-git-workflow", "step")
+            .into_iter()
+            .filter_map(|name| {
+                let key = |variable: &str| format!("api-git-workflow.{}.{}", name, variable);
+                let workflow = Workflow::parse(
+                    &name,
+                    config.get_str(&key("about")),
+                    &config.get_all(&key("arg")),
+                    &config.get_all(&key("flag")),
+                    &config.get_all(&key("step")),
+                );
+
+                match workflow {
+                    Ok(workflow) => Some(workflow),
+                    Err(err) => {
+                        eprintln!("Invalid workflow {}: {}", name, err);
+                        None
+                    }
+                }
+            })
+            .collect()
+    }
+
+    fn parse(
+        name: &str,
+        about: Option<String>,
+        args: &[String],
+        flags: &[String],
+        steps: &[String],
+    ) -> Result<Workflow, String> {
+        if BUILT_IN.contains(&name) {
+            return Err(format!("`{}` is a built in command", name));
+        }
+
+        let flags = flags
+            .iter()
+            .map(|flag| {
+                let mut parts = flag.splitn(2, '=');
+                Flag {
+                    name: parts.next().unwrap_or("").trim().to_string(),
+                    default: parts.next().map(|default| default.trim().to_string()),
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let names = args
+            .iter()
+            .map(String::as_str)
+            .chain(flags.iter().map(|flag| flag.name.as_str()));
+        for arg in names {
+            if arg.is_empty() {
+                return Err("arguments need a name".to_string());
+            }
+            if RESERVED_ARGS.contains(&arg) {
+                return Err(format!("`{}` is an option of every command", arg));
+            }
+        }
+
+        let steps = steps
+            .iter()
+            .map(|step| parse_step(step))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let known = args
+            .iter()
+            .chain(flags.iter().map(|flag| &flag.name))
+            .collect::<Vec<_>>();
+        for step in &steps {
+            for placeholder in placeholders(&step.arg) {
+                if !known.iter().any(|name| **name == placeholder) {
+                    return Err(format!("unknown argument `{{{}}}`", placeholder));
+                }
+            }
+        }
+
+        Ok(Workflow {
+            name: name.to_string(),
+            about,
+            args: args.to_vec(),
+            flags,
+            steps,
+        })
+    }
+
+    /// The steps with `{NAME}` replaced by the values of the arguments.
+    pub fn chain(&self, values: &HashMap<String, String>) -> CommandChain {
+        let mut c = CommandChain::new();
+        for step in &self.steps {
+            let words = step
+                .arg
+                .split_whitespace()
+                .map(|word| interpolate(word, values, str::to_string))
+                .collect();
+            let arg = match step.kind {
+                StepKind::Run => interpolate(&step.arg, values, shell_quote),
+                _ => interpolate(&step.arg, values, str::to_string),
+            };
+            add_step(&mut c, step.kind, arg, words);
+        }
+        c
+    }
+}
+
+/// A chain from steps written like the ones in workflows, such as `checkout master`.
+pub fn chain_from_steps(steps: &[String]) -> Result<CommandChain, String> {
+    let mut c = CommandChain::new();
+    for step in steps {
+        let step = parse_step(step)?;
+        let words = step.arg.split_whitespace().map(String::from).collect();
+        add_step(&mut c, step.kind, step.arg, words);
+    }
+    Ok(c)
+}
+
+/// Add a step. `arg` is used as it is by `run` and `confirm`, git steps get `words` as their
+/// arguments.
+fn add_step(c: &mut CommandChain, kind: StepKind, arg: String, words: Vec<String>) {
+    let git = |command: &str| {
+        let mut args = command
+            .split(' ')
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+        args.extend(words.iter().cloned());
+        Git::from(args)
+    };
+
+    match kind {
+        StepKind::Checkout => c.add(git("checkout")),
+        StepKind::Pull => c.add(Git::pull()),
+        StepKind::Fetch => c.add(Git::fetch()),
+        StepKind::Branch => c.add(git("branch")),
+        StepKind::Merge => c.add(git("merge --no-edit")),
+        StepKind::FastForwardMerge => c.add(git("merge --ff-only")),
+        StepKind::Rebase => c.add(git("rebase")),
+        StepKind::Push if words.is_empty() => c.add(Git::push()),
+        StepKind::Push => c.add(git("push --set-upstream origin")),
+        StepKind::Run => c.add(Run { shell: arg }),
+        StepKind::Confirm => c.add(Confirm { question: arg }),
+    }
+}
+
+fn parse_step(step: &str) -> Result<StepTemplate, String> {
+    let step = step.trim();
+    let (kind, arg) = match step.find(char::is_whitespace) {
+        Some(idx) => (&step[..idx], step[idx..].trim()),
+        None => (step, ""),
+    };
+
+    let kind = match kind {
+        "checkout" => StepKind::Checkout,
+        "pull" => StepKind::Pull,
+        "fetch" => StepKind::Fetch,
+        "branch" => StepKind::Branch,
+        "merge" => StepKind::Merge,
+        "ff-merge" => StepKind::FastForwardMerge,
+        "rebase" => StepKind::Rebase,
+        "push" => StepKind::Push,
+        "run" => StepKind::Run,
+        "confirm" => StepKind::Confirm,
+        other => return Err(format!("unknown step `{}`", other)),
+    };
+
+    let takes_arg = !matches!(kind, StepKind::Pull | StepKind::Fetch);
+    let needs_arg = takes_arg && kind != StepKind::Push;
+    if needs_arg && arg.is_empty() {
+        return Err(format!("`{}` needs an argument", step));
+    }
+    if !takes_arg && !arg.is_empty() {
+        return Err(format!("`{}` doesn't take an argument", step));
+    }
+
+    Ok(StepTemplate {
+        kind,
+        arg: arg.to_string(),
+    })
+}
+
+fn placeholder_regex() -> Regex {
+    Regex::new(r"\{([A-Za-z0-9_-]+)\}").expect("invalid regex")
+}
+
+fn placeholders(template: &str) -> Vec<String> {
+    placeholder_regex()
+        .captures_iter(template)
+        .map(|captures| captures[1].to_string())
+        .collect()
+}
+
+/// Replace `{NAME}` with the value of the argument, passed through `escape`.
+fn interpolate(
+    template: &str,
+    values: &HashMap<String, String>,
+    escape: fn(&str) -> String,
+) -> String {
+    placeholder_regex()
+        .replace_all(template, |captures: &Captures| {
+            escape(values.get(&captures[1]).map(String::as_str).unwrap_or(""))
+        })
+        .into_owned()
+}
+
+/// Quote a value so `sh` sees it as a single word.
+fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+/// Run a shell command from a workflow.
+struct Run {
+    shell: String,
+}
+
+impl Step for Run {
+    fn run_step(&self) -> RunStepResult {
+        let status = std::process::Command::new("sh")
+            .arg("-c")
+            .arg(&self.shell)
+            .status()
+            .expect("failed to execute process");
+
+        if status.success() {
+            RunStepResult::Ok
+        } else {
+            RunStepResult::Err
+        }
+    }
+
+    fn as_string(&self) -> String {
+        self.shell.clone()
+    }
+}
+
+/// Ask before continuing. Answering no stops the workflow.
+struct Confirm {
+    question: String,
+}
+
+impl Step for Confirm {
+    fn run_step(&self) -> RunStepResult {
+        match confirm(&self.question, ConfirmDefault::No) {
+            Ok(true) => RunStepResult::Ok,
+            Ok(false) => RunStepResult::Err,
+            Err(err) => {
+                eprintln!("{}", err);
+                RunStepResult::Err
+            }
+        }
+    }
+
+    fn as_string(&self) -> String {
+        format!("confirm: {}?", self.question)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    #[allow(unused_imports)]
+    use super::*;
+
+    fn strings(values: &[&str]) -> Vec<String> {
+        values.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse_and_interpolate() {
+        let workflow = Workflow::parse(
+            "release",
+            None,
+            &strings(&["VERSION"]),
+            &strings(&["base=master"]),
+            &strings(&[
+                "checkout {base}",
+                "pull",
+                "branch release-{VERSION}",
+                "push",
+            ]),
+        )
+        .unwrap();
+
+        assert_eq!(workflow.flags[0].default.as_deref(), Some("master"));
+
+        let mut values = HashMap::new();
+        values.insert("VERSION".to_string(), "1.2".to_string());
+        values.insert("base".to_string(), "develop".to_string());
+        assert_eq!(
+            interpolate(&workflow.steps[2].arg, &values, str::to_string),
+            "release-1.2"
+        );
+    }
+
+    #[test]
+    fn test_values_are_quoted_in_run_steps() {
+        let workflow = Workflow::parse(
+            "release",
+            None,
+            &strings(&["VERSION"]),
+            &[],
+            &strings(&["run echo {VERSION}"]),
+        )
+        .unwrap();
+
+        let mut values = HashMap::new();
+        values.insert("VERSION".to_string(), "1.2; rm -rf 'x'".to_string());
+        assert_eq!(
+            workflow.chain(&values).record_from_step(0),
+            vec![r#"echo '1.2; rm -rf '\''x'\'''"#]
+        );
+    }
+
+    #[test]
+    fn test_invalid_workflows() {
+        let parse = |name: &str, steps: &[&str]| {
+            Workflow::parse(name, None, &strings(&["VERSION"]), &[], &strings(steps))
+        };
+
+        assert!(parse("release", &["deploy"]).is_err());
+        assert!(parse("release", &["checkout"]).is_err());
+        assert!(parse("release", &["pull origin"]).is_err());
+        assert!(parse("release", &["checkout {BRANCH}"]).is_err());
+        assert!(parse("merge", &["pull"]).is_err());
+    }
+
+    #[test]
+    fn test_reserved_argument_names() {
+        let parse = |args: &[&str], flags: &[&str]| {
+            Workflow::parse("release", None, &strings(args), &strings(flags), &[])
+        };
+
+        assert!(parse(&["dry-run"], &[]).is_err());
+        assert!(parse(&[], &["from-step=1"]).is_err());
+        assert!(parse(&[], &["yes"]).is_err());
+        assert!(parse(&["VERSION"], &["base=master"]).is_ok());
+    }
+}
@@ -0,0 +1,181 @@
+//! A scratch repo with bare `origin`, `staging` and `development` remotes, for running api-git
+//! end to end.
+
+#![allow(dead_code)]
+
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+use tempfile::TempDir;
+
+pub struct TestRepo {
+    dir: TempDir,
+}
+
+impl TestRepo {
+    /// A repo with one commit on `master`, and `staging` and `develop` branched from it. All three
+    /// are pushed to origin.
+    pub fn new() -> TestRepo {
+        let repo = TestRepo {
+            dir: tempfile::tempdir().expect("failed to create tempdir"),
+        };
+
+        for remote in &["origin", "staging", "development"] {
+            repo.run_git(
+                repo.dir.path(),
+                &[
+                    "init",
+                    "-q",
+                    "--bare",
+                    "-b",
+                    "master",
+                    &format!("{}.git", remote),
+                ],
+            );
+        }
+
+        repo.run_git(repo.dir.path(), &["init", "-q", "-b", "master", "work"]);
+        repo.git("config user.name Tester");
+        repo.git("config user.email tester@example.com");
+        for remote in &["origin", "staging", "development"] {
+            repo.git(&format!("remote add {} ../{}.git", remote, remote));
+        }
+
+        repo.commit("README", "hello\n");
+        repo.git("push -q -u origin master");
+        for branch in &["staging", "develop"] {
+            repo.git(&format!("branch {}", branch));
+            repo.git(&format!("push -q -u origin {}", branch));
+        }
+
+        repo
+    }
+
+    pub fn path(&self) -> PathBuf {
+        self.dir.path().join("work")
+    }
+
+    pub fn remote_path(&self, remote: &str) -> PathBuf {
+        self.dir.path().join(format!("{}.git", remote))
+    }
+
+    /// Run git in the working repo and return its trimmed output. Panics if it fails.
+    pub fn git(&self, args: &str) -> String {
+        let args = args.split_whitespace().collect::<Vec<_>>();
+        self.run_git(&self.path(), &args)
+    }
+
+    /// Run api-git in the working repo. Nothing is read from stdin, so prompts get no answer.
+    pub fn api_git(&self, args: &str) -> Output {
+        let output = self
+            .command(env!("CARGO_BIN_EXE_api-git"), &self.path())
+            .args(args.split_whitespace())
+            .output()
+            .expect("failed to run api-git");
+
+        println!("{}", String::from_utf8_lossy(&output.stdout));
+        eprintln!("{}", String::from_utf8_lossy(&output.stderr));
+        output
+    }
+
+    /// Run api-git and return its stdout.
+    pub fn api_git_stdout(&self, args: &str) -> String {
+        String::from_utf8_lossy(&self.api_git(args).stdout).into_owned()
+    }
+
+    /// Write a file and commit it on the current branch. Returns the new commit.
+    pub fn commit(&self, file: &str, contents: &str) -> String {
+        std::fs::write(self.path().join(file), contents).expect("failed to write file");
+        self.git(&format!("add {}", file));
+        self.git(&format!("commit -q -m update-{}", file));
+        self.git("rev-parse HEAD")
+    }
+
+    /// The commit a rev points to in the working repo, if it exists.
+    pub fn rev(&self, rev: &str) -> Option<String> {
+        self.try_rev(&self.path(), rev)
+    }
+
+    /// The commit a branch points to in one of the bare remotes, if it exists.
+    pub fn remote_rev(&self, remote: &str, branch: &str) -> Option<String> {
+        self.try_rev(&self.remote_path(remote), &format!("refs/heads/{}", branch))
+    }
+
+    pub fn current_branch(&self) -> String {
+        self.git("rev-parse --abbrev-ref HEAD")
+    }
+
+    /// Whether `ancestor` is reachable from `rev` in the working repo.
+    pub fn contains(&self, rev: &str, ancestor: &str) -> bool {
+        self.command("git", &self.path())
+            .args(["merge-base", "--is-ancestor", ancestor, rev])
+            .status()
+            .expect("failed to run git")
+            .success()
+    }
+
+    /// Every ref in the working repo and the remotes, to check that nothing changed.
+    pub fn all_refs(&self) -> String {
+        let mut refs = self.git("for-each-ref");
+        for remote in &["origin", "staging", "development"] {
+            refs.push_str(&self.run_git(&self.remote_path(remote), &["for-each-ref"]));
+        }
+        refs
+    }
+
+    fn try_rev(&self, dir: &Path, rev: &str) -> Option<String> {
+        let output = self
+            .command("git", dir)
+            .args(["rev-parse", "--verify", "-q", rev])
+            .output()
+            .expect("failed to run git");
+
+        if output.status.success() {
+            Some(String::from_utf8_lossy(&output.stdout).trim().to_string())
+        } else {
+            None
+        }
+    }
+
+    fn run_git(&self, dir: &Path, args: &[&str]) -> String {
+        let output = self
+            .command("git", dir)
+            .args(args)
+            .output()
+            .expect("failed to run git");
+
+        if !output.status.success() {
+            panic!(
+                "`git {}` failed: {}",
+                args.join(" "),
+                String::from_utf8_lossy(&output.stderr)
+            );
+        }
+
+        String::from_utf8_lossy(&output.stdout).trim().to_string()
+    }
+
+    /// A command isolated from the user's git config.
+    fn command(&self, program: &str, dir: &Path) -> Command {
+        let mut command = Command::new(program);
+        command
+            .current_dir(dir)
+            .stdin(Stdio::null())
+            .env("HOME", self.dir.path())
+            .env("GIT_CONFIG_NOSYSTEM", "1")
+            .env("GIT_EDITOR", "true")
+            .env("CLICOLOR", "0")
+            .env_remove("GIT_DIR")
+            .env_remove("GIT_WORK_TREE");
+        command
+    }
+}
+
+/// The step given to `--from-step` in the retry command printed by a failed run.
+pub fn retry_step(stdout: &str) -> usize {
+    let start = stdout.find("--from-step ").expect("no retry command") + "--from-step ".len();
+    stdout[start..]
+        .split_whitespace()
+        .next()
+        .and_then(|step| step.parse().ok())
+        .expect("invalid retry command")
+}
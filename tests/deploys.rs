@@ -0,0 +1,47 @@
+mod common;
+
+use common::TestRepo;
+
+#[test]
+fn test_deploys_are_recorded_in_notes() {
+    let repo = TestRepo::new();
+    for branch in &["feature", "fix"] {
+        repo.git("checkout -q master");
+        repo.git(&format!("checkout -q -b {}", branch));
+        repo.commit(&format!("{}.txt", branch), "change\n");
+        repo.git(&format!("push -q -u origin {}", branch));
+        repo.api_git(&format!("on-staging {}", branch));
+    }
+
+    assert!(repo
+        .git("ls-remote origin refs/notes/api-git/deploys")
+        .contains("refs/notes/api-git/deploys"));
+    let note = repo.git("notes --ref api-git/deploys show staging");
+    assert!(note.contains(" staging fix Tester <tester@example.com>"));
+
+    let timeline = repo.api_git_stdout("deploys staging");
+    let fix = timeline.find("fix  Tester").unwrap();
+    let feature = timeline.find("feature  Tester").unwrap();
+    assert!(fix < feature, "newest deploy first");
+    assert!(timeline.contains("commits since the previous deploy"));
+    assert!(timeline.contains("The first recorded deploy to staging"));
+
+    // Deploys only the local notes have are kept when origin's are merged in
+    let note = repo.path().join(".git/note.txt");
+    std::fs::write(
+        &note,
+        "2026-01-01T00:00:00.000+00:00 staging local Tester <tester@example.com>\n",
+    )
+    .unwrap();
+    repo.git(&format!(
+        "notes --ref api-git/deploys add -F {} master",
+        note.display()
+    ));
+    assert!(repo
+        .api_git_stdout("deploys staging")
+        .contains("local  Tester"));
+
+    assert!(repo
+        .api_git_stdout("deploys develop")
+        .contains("No deploys to develop have been recorded"));
+}
@@ -0,0 +1,90 @@
+mod common;
+
+use common::TestRepo;
+
+#[test]
+fn test_freeze_blocks_merges_until_it_ends() {
+    let repo = TestRepo::new();
+    repo.git("checkout -q -b feature");
+    let tip = repo.commit("feature.txt", "feature\n");
+    repo.git("checkout -q master");
+
+    repo.api_git("freeze start --reason release-42");
+    assert!(repo
+        .git("ls-remote origin refs/api-git/freezes/*")
+        .contains("refs/api-git/freezes/master"));
+    assert!(repo
+        .api_git_stdout("freeze status")
+        .contains("master is frozen since"));
+
+    let output = repo.api_git("merge feature");
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("release-42"));
+    assert!(!repo.contains("origin/master", &tip));
+
+    // Resuming doesn't skip the check
+    assert!(!repo.api_git("merge feature --from-step 3").status.success());
+    assert!(!repo.contains("origin/master", &tip));
+
+    // The freeze is already there, so starting it again fails
+    assert!(repo
+        .api_git_stdout("freeze start --reason again")
+        .contains("Step 0 failed"));
+
+    repo.api_git("freeze end");
+    assert_eq!(
+        repo.api_git_stdout("freeze status").trim(),
+        "Nothing is frozen"
+    );
+
+    repo.api_git("merge feature");
+    assert!(repo.contains("origin/master", &tip));
+}
+
+#[test]
+fn test_override_freeze() {
+    let repo = TestRepo::new();
+    repo.git("checkout -q -b feature");
+    let tip = repo.commit("feature.txt", "feature\n");
+    repo.git("push -q -u origin feature");
+
+    repo.api_git("freeze start --reason demo staging");
+
+    repo.api_git("on-staging --no-ship feature");
+    assert!(!repo.contains("origin/staging", &tip));
+
+    let stdout = repo.api_git_stdout("on-staging --no-ship --override-freeze urgent feature");
+    assert!(stdout.contains("Overriding the freeze: urgent"));
+    assert!(repo.contains("origin/staging", &tip));
+
+    let audit = std::fs::read_to_string(repo.path().join(".git/api-git/audit.jsonl")).unwrap();
+    let last = audit.lines().last().unwrap();
+    assert!(last.contains(r#""freeze_override":"urgent""#));
+
+    let id = last
+        .split(r#""id":""#)
+        .nth(1)
+        .unwrap()
+        .split('"')
+        .next()
+        .unwrap();
+    assert!(repo
+        .api_git_stdout(&format!("history show {}", id))
+        .contains("Freeze overridden: urgent"));
+}
+
+#[test]
+fn test_end_freeze() {
+    let repo = TestRepo::new();
+    repo.git("config api-git.confirmRisky true");
+
+    repo.api_git("freeze start --reason release-42");
+    let output = repo.api_git("freeze end");
+    assert!(output.status.success());
+    assert!(!String::from_utf8_lossy(&output.stdout).contains("failed"));
+    assert_eq!(repo.git("ls-remote origin refs/api-git/freezes/*"), "");
+
+    let stdout = repo.api_git_stdout("freeze end");
+    assert!(stdout.contains("master isn't frozen"));
+    assert!(!stdout.contains("failed"));
+}
@@ -0,0 +1,96 @@
+mod common;
+
+use common::TestRepo;
+
+const EMPTY_TREE: &str = "4b825dc642cb6eb9a060e54bf8d69288fbee4904";
+
+fn locks(repo: &TestRepo) -> String {
+    repo.git("ls-remote origin refs/api-git/locks/*")
+}
+
+#[test]
+fn test_on_staging_takes_and_releases_the_lock() {
+    let repo = TestRepo::new();
+    repo.git("checkout -q -b feature");
+    let tip = repo.commit("feature.txt", "feature\n");
+    repo.git("push -q -u origin feature");
+
+    let stdout = repo.api_git_stdout("on-staging feature");
+    assert!(stdout.contains("lock staging on origin for feature"));
+    assert!(stdout.contains("release the lock on staging"));
+    assert!(repo.contains("origin/staging", &tip));
+    assert_eq!(locks(&repo), "");
+}
+
+#[test]
+fn test_the_lock_is_released_when_a_step_before_the_merge_fails() {
+    let repo = TestRepo::new();
+    repo.git("checkout -q -b feature");
+    repo.commit("feature.txt", "feature\n");
+
+    // Already ours, so it's taken again for this branch
+    repo.api_git("lock steal staging");
+    assert_ne!(locks(&repo), "");
+
+    // feature has no upstream, so the first push fails
+    let stdout = repo.api_git_stdout("on-staging feature");
+    assert!(stdout.contains("You already have the lock on staging"));
+    assert!(stdout.contains("Step 1 failed"));
+    assert!(stdout.contains("Nothing was deployed, so the lock is released"));
+    assert_eq!(locks(&repo), "");
+}
+
+#[test]
+fn test_someone_elses_lock() {
+    let repo = TestRepo::new();
+    repo.git("checkout -q -b feature");
+    let tip = repo.commit("feature.txt", "feature\n");
+    repo.git("push -q -u origin feature");
+
+    let lock = repo.git(&format!(
+        "-c user.name=Bob -c user.email=bob@example.com commit-tree {} -m Lock",
+        EMPTY_TREE
+    ));
+    repo.git(&format!(
+        "push -q origin {}:refs/api-git/locks/staging",
+        lock.trim()
+    ));
+
+    let stdout = repo.api_git_stdout("on-staging feature");
+    assert!(stdout.contains("Step 0 failed"));
+    assert!(!repo.contains("origin/staging", &tip));
+    assert!(repo
+        .api_git_stdout("lock status")
+        .contains("staging is locked by Bob"));
+
+    // It isn't ours to release
+    assert!(repo
+        .api_git_stdout("lock release staging")
+        .contains("Step 0 failed"));
+    assert!(locks(&repo).contains(lock.trim()));
+
+    repo.api_git("lock steal staging");
+    assert!(repo
+        .api_git_stdout("lock status")
+        .contains("staging is locked by Tester"));
+
+    repo.api_git("on-staging feature");
+    assert!(repo.contains("origin/staging", &tip));
+    assert_eq!(locks(&repo), "");
+}
+
+#[test]
+fn test_steal_outside_a_repo() {
+    let dir = tempfile::tempdir().unwrap();
+
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_api-git"))
+        .current_dir(dir.path())
+        .args(["lock", "steal", "staging"])
+        .output()
+        .unwrap();
+
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Not in a git repo"), "{}", stderr);
+    assert!(!stderr.contains("panicked"), "{}", stderr);
+}
@@ -0,0 +1,311 @@
+mod common;
+
+use common::{retry_step, TestRepo};
+
+fn feature_branch(repo: &TestRepo, file: &str) -> String {
+    repo.git("checkout -q -b feature");
+    let tip = repo.commit(file, "feature\n");
+    repo.git("push -q -u origin feature");
+    repo.git("checkout -q master");
+    tip
+}
+
+#[test]
+fn test_merge_pushes_and_cleans_up() {
+    let repo = TestRepo::new();
+    let tip = feature_branch(&repo, "feature.txt");
+
+    repo.api_git("merge feature");
+
+    assert_eq!(repo.rev("master"), Some(tip.clone()));
+    assert_eq!(repo.remote_rev("origin", "master"), Some(tip.clone()));
+    assert_eq!(repo.rev("feature"), None);
+    assert_eq!(repo.remote_rev("origin", "feature"), None);
+    assert_eq!(repo.rev("refs/api-git/archive/feature"), Some(tip.clone()));
+    assert_eq!(
+        repo.rev("refs/api-git/archive-origin/feature"),
+        Some(tip.clone())
+    );
+    assert_eq!(repo.current_branch(), "master");
+
+    for branch in &["staging", "develop"] {
+        assert!(repo.contains(&format!("origin/{}", branch), &tip));
+        assert_eq!(repo.rev(branch), repo.remote_rev("origin", branch));
+    }
+}
+
+#[test]
+fn test_merge_ends_on_the_starting_branch() {
+    let repo = TestRepo::new();
+    let tip = feature_branch(&repo, "feature.txt");
+    repo.git("checkout -q -b notes");
+
+    repo.api_git("merge feature");
+
+    assert_eq!(repo.remote_rev("origin", "master"), Some(tip));
+    assert_eq!(repo.current_branch(), "notes");
+}
+
+#[test]
+fn test_merge_checked_out_branch_that_isnt_pushed() {
+    let repo = TestRepo::new();
+    repo.git("checkout -q -b feature");
+    let tip = repo.commit("feature.txt", "feature\n");
+
+    repo.api_git("merge --yes feature");
+
+    assert_eq!(repo.remote_rev("origin", "master"), Some(tip));
+    assert_eq!(repo.rev("feature"), None);
+}
+
+#[test]
+fn test_merge_rebases_onto_master() {
+    let repo = TestRepo::new();
+    let tip = feature_branch(&repo, "feature.txt");
+    let master = repo.commit("master.txt", "master\n");
+    repo.git("push -q origin master");
+
+    repo.api_git("merge feature");
+
+    let merged = repo.remote_rev("origin", "master").unwrap();
+    assert_ne!(merged, tip);
+    assert!(repo.contains(&merged, &master));
+    assert_eq!(
+        repo.git("show -s --format=%s origin/master"),
+        "update-feature.txt"
+    );
+}
+
+#[test]
+fn test_conflict_and_resume() {
+    let repo = TestRepo::new();
+    feature_branch(&repo, "README");
+    let master = repo.commit("README", "master\n");
+    repo.git("push -q origin master");
+
+    let stdout = repo.api_git_stdout("merge feature");
+    assert_eq!(repo.remote_rev("origin", "master"), Some(master.clone()));
+    assert!(repo.rev("feature").is_some());
+
+    // Resolve the rebase conflict and pick up where it failed
+    std::fs::write(repo.path().join("README"), "resolved\n").unwrap();
+    repo.git("add README");
+    repo.git("rebase --continue");
+
+    repo.api_git(&format!(
+        "merge --into master feature --from-step {}",
+        retry_step(&stdout)
+    ));
+
+    let merged = repo.remote_rev("origin", "master").unwrap();
+    assert!(repo.contains(&merged, &master));
+    assert_eq!(repo.git("show origin/master:README"), "resolved");
+    assert_eq!(repo.rev("feature"), None);
+
+    // The commit on origin/feature was changed while resolving the conflict, so it's kept
+    assert!(repo.remote_rev("origin", "feature").is_some());
+}
+
+#[test]
+fn test_fresh_run_is_refused_while_a_failed_run_has_changes_stashed() {
+    let repo = TestRepo::new();
+    feature_branch(&repo, "README");
+    repo.commit("README", "master\n");
+    repo.git("push -q origin master");
+    std::fs::write(repo.path().join("notes.txt"), "local\n").unwrap();
+
+    repo.api_git("merge --autostash feature");
+    assert!(!repo.path().join("notes.txt").exists());
+    repo.git("rebase --abort");
+
+    let output = repo.api_git("merge feature");
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .contains("A previous run left local changes stashed in stash@{0}"));
+    assert!(repo.git("stash list").contains("api-git-autostash"));
+
+    repo.git("checkout -q master");
+    repo.git("stash pop -q");
+    assert_eq!(
+        std::fs::read_to_string(repo.path().join("notes.txt")).unwrap(),
+        "local\n"
+    );
+}
+
+#[test]
+fn test_conflicting_downstream_merge_is_rolled_back() {
+    let repo = TestRepo::new();
+    repo.git("checkout -q staging");
+    let staging = repo.commit("README", "staging\n");
+    repo.git("push -q origin staging");
+    repo.git("checkout -q master");
+    let tip = feature_branch(&repo, "README");
+
+    repo.api_git("merge feature");
+
+    assert_eq!(repo.remote_rev("origin", "master"), Some(tip.clone()));
+    assert_eq!(repo.remote_rev("origin", "staging"), Some(staging.clone()));
+    assert_eq!(repo.rev("staging"), Some(staging));
+    assert!(repo.contains("origin/develop", &tip));
+
+    // Only the refs that moved are in the audit log
+    let audit = std::fs::read_to_string(repo.path().join(".git/api-git/audit.jsonl")).unwrap();
+    let last = audit.lines().last().unwrap();
+    assert!(last.contains(r#""origin/develop":"#));
+    assert!(!last.contains(r#""staging":"#));
+}
+
+#[test]
+fn test_failed_downstream_merge_into_a_branch_only_on_origin() {
+    let repo = TestRepo::new();
+    repo.git("checkout -q staging");
+    let staging = repo.commit("README", "staging\n");
+    repo.git("push -q origin staging");
+    repo.git("checkout -q master");
+    repo.git("branch -q -D staging");
+    let tip = feature_branch(&repo, "README");
+
+    repo.api_git("merge feature");
+
+    assert_eq!(repo.remote_rev("origin", "master"), Some(tip.clone()));
+    assert_eq!(repo.remote_rev("origin", "staging"), Some(staging));
+    assert_eq!(repo.rev("staging"), None);
+    assert!(repo.contains("origin/develop", &tip));
+}
+
+#[test]
+fn test_merges_after_a_failed_downstream_merge_are_skipped() {
+    let repo = TestRepo::new();
+    repo.git("config api-git.propagate master->staging");
+    repo.git("config --add api-git.propagate staging->develop");
+    repo.git("checkout -q staging");
+    repo.commit("README", "staging\n");
+    repo.git("push -q origin staging");
+    repo.git("checkout -q master");
+    let develop = repo.rev("develop");
+    let tip = feature_branch(&repo, "README");
+
+    let stdout = repo.api_git_stdout("merge feature");
+
+    assert_eq!(repo.remote_rev("origin", "master"), Some(tip));
+    assert_eq!(repo.remote_rev("origin", "develop"), develop);
+    assert!(stdout.contains("skipped   because master into staging didn't succeed"));
+    assert!(stdout.contains("--from-step"));
+
+    let audit = std::fs::read_to_string(repo.path().join(".git/api-git/audit.jsonl")).unwrap();
+    assert!(audit
+        .lines()
+        .last()
+        .unwrap()
+        .contains(r#""succeeded":false"#));
+}
+
+#[test]
+fn test_dry_run_changes_nothing() {
+    let repo = TestRepo::new();
+    feature_branch(&repo, "feature.txt");
+    let refs = repo.all_refs();
+
+    let stdout = repo.api_git_stdout("merge feature --dry-run");
+
+    assert!(stdout.contains("git rebase master"));
+    assert_eq!(repo.all_refs(), refs);
+    assert_eq!(repo.current_branch(), "master");
+}
+
+#[test]
+fn test_prompts_without_a_terminal() {
+    let repo = TestRepo::new();
+    repo.git("checkout -q -b feature");
+    let tip = repo.commit("feature.txt", "feature\n");
+
+    let output = repo.api_git("merge");
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("isn't a terminal"));
+
+    // Merging the current branch defaults to no
+    repo.api_git("merge --no-input");
+    assert_eq!(repo.rev("feature"), Some(tip.clone()));
+
+    repo.api_git("merge --yes");
+    assert_eq!(repo.remote_rev("origin", "master"), Some(tip));
+}
+
+#[test]
+fn test_confirm_shows_the_plan() {
+    let repo = TestRepo::new();
+    repo.git("checkout -q -b feature");
+    let tip = repo.commit("feature.txt", "feature\n");
+    repo.git("checkout -q master");
+
+    let stdout = repo.api_git_stdout("merge --confirm --no-input feature");
+    assert!(stdout.contains("Merging feature into master"));
+    assert!(stdout.contains("1 commit:"));
+    assert!(stdout.contains("1 file changed, 1 insertion(+), 0 deletions(-)"));
+    assert!(stdout.contains("feature is up to date with master, no rebase needed"));
+    assert!(stdout.contains("git push --atomic origin master staging develop :feature"));
+    assert!(stdout.contains("Do you want to run these steps? y/N no"));
+    assert_eq!(repo.rev("feature"), Some(tip));
+    assert_ne!(repo.remote_rev("origin", "master"), repo.rev("feature"));
+}
+
+#[test]
+fn test_the_plan_numbers_the_steps_as_they_run() {
+    let repo = TestRepo::new();
+    repo.git("checkout -q -b feature");
+    repo.commit("feature.txt", "feature\n");
+    repo.git("checkout -q master");
+
+    let stdout = repo.api_git_stdout("merge --confirm --autostash --no-input feature");
+    assert!(stdout.contains("   0  git stash push --include-untracked"));
+    assert!(stdout.contains("git stash pop"));
+    assert!(!repo.path().join(".git/api-git/journal.json").exists());
+}
+
+#[test]
+fn test_policy_violations_stop_the_merge_unless_overridden() {
+    let repo = TestRepo::new();
+    repo.git("config api-git.policy.noFixups true");
+    repo.git("checkout -q -b feature");
+    repo.commit("feature.txt", "feature\n");
+    repo.git("commit -q --allow-empty -m fixup!");
+    let tip = repo.rev("feature").unwrap();
+    repo.git("checkout -q master");
+
+    let output = repo.api_git("merge feature");
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("\"fixup!\" has to be squashed before merging"));
+    assert_eq!(repo.rev("feature"), Some(tip.clone()));
+
+    repo.api_git("merge --override-policy hotfix feature");
+    assert!(repo.contains("origin/master", &tip));
+
+    let audit = std::fs::read_to_string(repo.path().join(".git/api-git/audit.jsonl")).unwrap();
+    let lines = audit.lines().collect::<Vec<_>>();
+    assert_eq!(lines.len(), 2);
+    assert!(lines[0].contains(r#""policy_refused":["#));
+    assert!(lines[0].contains(r#""succeeded":false"#));
+    assert!(lines[1].contains(r#""policy_override":{"reason":"hotfix""#));
+}
+
+#[test]
+fn test_policy_freeze_window_stops_the_merge_unless_overridden() {
+    let repo = TestRepo::new();
+    repo.git("config api-git.policy.freeze 2000-01-01..2999-12-31");
+    let tip = feature_branch(&repo, "feature.txt");
+
+    let output = repo.api_git("merge feature");
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .contains("master is frozen from 2000-01-01 to 2999-12-31"));
+    assert!(!repo.contains("origin/master", &tip));
+
+    repo.api_git("merge --override-policy release feature");
+    assert!(repo.contains("origin/master", &tip));
+
+    let audit = std::fs::read_to_string(repo.path().join(".git/api-git/audit.jsonl")).unwrap();
+    let last = audit.lines().last().unwrap();
+    assert!(last.contains(r#""policy_override":{"reason":"release""#));
+    assert!(last.contains("master is frozen from 2000-01-01 to 2999-12-31"));
+}
@@ -0,0 +1,56 @@
+mod common;
+
+use common::TestRepo;
+
+#[test]
+fn test_on_staging_merges_and_deploys() {
+    let repo = TestRepo::new();
+    repo.git("checkout -q -b feature");
+    let tip = repo.commit("feature.txt", "feature\n");
+    repo.git("push -q -u origin feature");
+
+    repo.api_git("on-staging feature");
+
+    let staging = repo.remote_rev("origin", "staging").unwrap();
+    assert!(repo.contains(&staging, &tip));
+    assert_eq!(repo.remote_rev("staging", "master"), Some(staging));
+    assert_eq!(repo.current_branch(), "feature");
+}
+
+#[test]
+fn test_no_ship_does_not_deploy() {
+    let repo = TestRepo::new();
+    repo.git("checkout -q -b feature");
+    repo.commit("feature.txt", "feature\n");
+    repo.git("push -q -u origin feature");
+
+    repo.api_git("on-develop feature --no-ship");
+
+    assert!(repo.contains("origin/develop", "feature"));
+    assert_eq!(repo.remote_rev("development", "master"), None);
+}
+
+#[test]
+fn test_confirm_risky_steps() {
+    let repo = TestRepo::new();
+    repo.git("config api-git.confirmRisky true");
+    repo.git("checkout -q -b feature");
+    repo.commit("feature.txt", "feature\n");
+    repo.git("push -q -u origin feature");
+
+    let stdout = repo.api_git_stdout("on-staging --no-input feature");
+    assert!(stdout.contains("git push staging staging:master  [deploy]"));
+    assert!(stdout.contains("git push  [remote-write]"));
+    assert_eq!(repo.remote_rev("staging", "master"), None);
+
+    // Deploying without risky steps, or allowing them, doesn't ask
+    repo.api_git("on-staging --no-input --no-ship feature");
+    assert!(repo.contains("origin/staging", "feature"));
+    assert_eq!(repo.remote_rev("staging", "master"), None);
+
+    repo.api_git("on-staging --no-input --allow-destructive feature");
+    assert_eq!(
+        repo.remote_rev("staging", "master"),
+        repo.remote_rev("origin", "staging")
+    );
+}
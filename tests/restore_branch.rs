@@ -0,0 +1,23 @@
+mod common;
+
+use common::TestRepo;
+
+#[test]
+fn test_restore_branch_deleted_on_origin_by_someone_else() {
+    let repo = TestRepo::new();
+    repo.git("checkout -q --detach");
+    let tip = repo.commit("shared.txt", "shared\n");
+    repo.git("push -q origin HEAD:refs/heads/shared");
+    repo.git("fetch -q origin");
+    repo.git("checkout -q master");
+
+    repo.git(&format!(
+        "--git-dir {} branch -q -D shared",
+        repo.remote_path("origin").display()
+    ));
+
+    let stdout = repo.api_git_stdout("restore-branch --yes shared");
+
+    assert!(stdout.contains("origin's branch"));
+    assert_eq!(repo.rev("shared"), Some(tip));
+}
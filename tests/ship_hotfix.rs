@@ -0,0 +1,18 @@
+mod common;
+
+use common::TestRepo;
+
+#[test]
+fn test_ship_hotfix_pushes_master_and_propagates() {
+    let repo = TestRepo::new();
+    let hotfix = repo.commit("fix.txt", "fix\n");
+
+    repo.api_git("ship-hotfix");
+
+    assert_eq!(repo.remote_rev("origin", "master"), Some(hotfix.clone()));
+    for branch in &["staging", "develop"] {
+        let tip = repo.remote_rev("origin", branch).unwrap();
+        assert!(repo.contains(&tip, &hotfix));
+    }
+    assert_eq!(repo.current_branch(), "master");
+}
@@ -0,0 +1,45 @@
+mod common;
+
+use common::TestRepo;
+
+#[test]
+fn test_start_branches_from_updated_base() {
+    let repo = TestRepo::new();
+    repo.git("checkout -q develop");
+
+    repo.api_git("start add-login-page --push");
+
+    assert_eq!(repo.current_branch(), "add-login-page");
+    assert_eq!(repo.rev("add-login-page"), repo.rev("master"));
+    assert_eq!(
+        repo.remote_rev("origin", "add-login-page"),
+        repo.rev("master")
+    );
+}
+
+#[test]
+fn test_invalid_shared_config_is_reported() {
+    let repo = TestRepo::new();
+    std::fs::write(repo.path().join(".api-git"), "[api-git\nautostash\n").unwrap();
+
+    let output = repo.api_git("start add-login-page");
+
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Invalid"), "{}", stderr);
+    assert!(!stderr.contains("panicked"), "{}", stderr);
+    assert_eq!(repo.current_branch(), "master");
+}
+
+#[test]
+fn test_negative_max_length_is_rejected() {
+    let repo = TestRepo::new();
+    repo.git("config api-git.branch.maxLength -5");
+
+    let output = repo.api_git("start add-login-page");
+
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .contains("Invalid api-git.branch.maxLength: -5 is negative"));
+    assert_eq!(repo.current_branch(), "master");
+}